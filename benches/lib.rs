@@ -2,7 +2,9 @@
 
 extern crate test;
 
-use rcu_cell::RcuCell;
+use rcu_cell::{
+    EpochRcuCell, HazardRcuCell, LeftRight, RcuCell, RcuDomain, RcuValue, StripedRcuCell,
+};
 use test::Bencher;
 
 use std::sync::atomic::{AtomicUsize, Ordering};
@@ -17,6 +19,66 @@ fn rcu_read(b: &mut Bencher) {
     });
 }
 
+#[bench]
+fn rcu_cache_read(b: &mut Bencher) {
+    let rcu_cell = Arc::new(RcuCell::new(10));
+    let mut cache = rcu_cell.cache();
+    b.iter(|| {
+        let v = cache.load().as_ref().unwrap();
+        test::black_box(&**v);
+    });
+}
+
+#[bench]
+fn epoch_read_guard(b: &mut Bencher) {
+    let rcu_cell = Arc::new(EpochRcuCell::new(10));
+    b.iter(|| {
+        let v = rcu_cell.read_guard().unwrap();
+        test::black_box(&*v);
+    });
+}
+
+#[bench]
+fn hazard_read_guard(b: &mut Bencher) {
+    let rcu_cell = Arc::new(HazardRcuCell::new(10));
+    b.iter(|| {
+        let v = rcu_cell.read_guard().unwrap();
+        test::black_box(&*v);
+    });
+}
+
+#[bench]
+fn striped_read_guard(b: &mut Bencher) {
+    let rcu_cell = Arc::new(StripedRcuCell::new(10));
+    b.iter(|| {
+        let v = rcu_cell.read_guard().unwrap();
+        test::black_box(&*v);
+    });
+}
+
+#[bench]
+fn domain_read_lock(b: &mut Bencher) {
+    let domain = RcuDomain::with_membarrier();
+    let cell = domain.cell(10);
+    let thread = domain.register();
+    b.iter(|| {
+        let guard = thread.read_lock();
+        test::black_box(cell.read(&guard).unwrap());
+    });
+}
+
+#[bench]
+fn value_load(b: &mut Bencher) {
+    let value = RcuValue::new([1u64, 2, 3]);
+    b.iter(|| test::black_box(value.load()));
+}
+
+#[bench]
+fn left_right_read(b: &mut Bencher) {
+    let lr = LeftRight::<_, ()>::new(10);
+    b.iter(|| test::black_box(*lr.read()));
+}
+
 #[bench]
 fn rcu_write(b: &mut Bencher) {
     let rcu_cell = Arc::new(RcuCell::new(0));
@@ -28,6 +90,16 @@ fn rcu_write(b: &mut Bencher) {
     });
 }
 
+#[bench]
+fn value_store(b: &mut Bencher) {
+    let value = RcuValue::new(0usize);
+    let mut i = 0;
+    b.iter(|| {
+        i += 1;
+        value.store(i);
+    });
+}
+
 #[bench]
 fn read_update_1(b: &mut Bencher) {
     static REF: AtomicUsize = AtomicUsize::new(0);
@@ -278,3 +350,36 @@ fn rwlock_arc(b: &mut Bencher) {
         assert_eq!(REF.load(Ordering::Relaxed), 1001);
     });
 }
+
+// the readers on all the cpus read the same cell, compare the single word
+// link of `RcuCell` with the per-cpu stripes of `StripedRcuCell`
+fn read_scale<C: Sync>(b: &mut Bencher, cell: &C, read: impl Fn(&C) + Sync) {
+    let readers = std::thread::available_parallelism().map_or(4, |n| n.get());
+    b.iter(|| {
+        std::thread::scope(|s| {
+            for _ in 0..readers {
+                s.spawn(|| {
+                    for _ in 0..10000 {
+                        read(cell);
+                    }
+                });
+            }
+        });
+    });
+}
+
+#[bench]
+fn read_scale_link(b: &mut Bencher) {
+    let cell = RcuCell::new(10);
+    read_scale(b, &cell, |c| {
+        test::black_box(*c.read_guard().unwrap());
+    });
+}
+
+#[bench]
+fn read_scale_striped(b: &mut Bencher) {
+    let cell = StripedRcuCell::new(10);
+    read_scale(b, &cell, |c| {
+        test::black_box(*c.read_guard().unwrap());
+    });
+}
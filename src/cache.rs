@@ -0,0 +1,106 @@
+use core::fmt;
+
+use crate::pointer::RcuPointer;
+use crate::strategy::{SpinThenPark, WaitStrategy};
+use crate::RcuCellOf;
+
+/// A cached reader of the rcu cell, like the `Cache` of `arc-swap`.
+///
+/// The cache holds its own clone of the cell value together with the version
+/// it was read with. `load` only does a relaxed load of the cell version and
+/// returns the cached value if nothing was published since then, so it never
+/// touches the reader count of the cell in the common case. The value is
+/// reloaded through `read_versioned` when the cell is changed.
+///
+/// Since the cache keeps the old value alive until the next `load`, it would
+/// not block the writers but the old value may live longer than expected.
+///
+/// # Examples
+///
+/// ```
+/// use rcu_cell::RcuCell;
+///
+/// let cell = RcuCell::new(1);
+/// let mut cache = cell.cache();
+/// assert_eq!(cache.load().as_deref(), Some(&1));
+/// cell.write(2);
+/// assert_eq!(cache.load().as_deref(), Some(&2));
+/// ```
+pub struct RcuCache<'a, P: RcuPointer + Clone, W = SpinThenPark> {
+    cell: &'a RcuCellOf<P, W>,
+    value: P,
+    version: u64,
+}
+
+impl<'a, P: RcuPointer + Clone, W: WaitStrategy> RcuCache<'a, P, W> {
+    /// create a cache of the cell, the current value is read out
+    #[inline]
+    pub fn new(cell: &'a RcuCellOf<P, W>) -> Self {
+        let (value, version) = cell.read_versioned();
+        RcuCache {
+            cell,
+            value,
+            version,
+        }
+    }
+
+    /// return the latest value of the cell, the cached value is reused if
+    /// the cell is not changed since it's cached
+    #[inline]
+    pub fn load(&mut self) -> &P {
+        if self.cell.link.changed_since(self.version) {
+            self.reload();
+        }
+        &self.value
+    }
+
+    #[cold]
+    fn reload(&mut self) {
+        let (value, version) = self.cell.read_versioned();
+        self.value = value;
+        self.version = version;
+    }
+
+    /// return the cached value without checking the cell
+    #[inline]
+    pub fn cached(&self) -> &P {
+        &self.value
+    }
+
+    /// the version of the cached value
+    #[inline]
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// the cell that is cached
+    #[inline]
+    pub fn cell(&self) -> &'a RcuCellOf<P, W> {
+        self.cell
+    }
+}
+
+impl<P: RcuPointer + Clone, W> Clone for RcuCache<'_, P, W> {
+    fn clone(&self) -> Self {
+        RcuCache {
+            cell: self.cell,
+            value: self.value.clone(),
+            version: self.version,
+        }
+    }
+}
+
+impl<'a, P: RcuPointer + Clone, W: WaitStrategy> From<&'a RcuCellOf<P, W>> for RcuCache<'a, P, W> {
+    fn from(cell: &'a RcuCellOf<P, W>) -> Self {
+        RcuCache::new(cell)
+    }
+}
+
+impl<P: RcuPointer + Clone + fmt::Debug, W> fmt::Debug for RcuCache<'_, P, W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RcuCache")
+            .field("value", &self.value)
+            .field("version", &self.version)
+            .finish()
+    }
+}
@@ -0,0 +1,458 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ptr;
+use core::sync::atomic::{compiler_fence, fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::thread::JoinHandle;
+
+use crossbeam_utils::CachePadded;
+
+use crate::membarrier;
+use crate::park::Waiter;
+use crate::strategy::SpinThenYield;
+
+/// The callback that is called after a grace period
+pub type RcuCallback = Box<dyn FnOnce() + Send + 'static>;
+
+#[inline]
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The read side state of a registered thread
+struct Record {
+    // the grace period that the reader is started in, zero if not reading
+    ctr: CachePadded<AtomicUsize>,
+}
+
+struct Inner {
+    // the current grace period, it starts from one and never be zero
+    gp: CachePadded<AtomicUsize>,
+    // the readers don't need fences if the membarrier is used
+    membarrier: bool,
+    readers: Mutex<Vec<Arc<Record>>>,
+    // serialize the grace periods
+    gp_lock: Mutex<()>,
+    callbacks: Mutex<Vec<RcuCallback>>,
+    shutdown: AtomicBool,
+}
+
+impl Inner {
+    // the full barrier that pairs with the read side barrier
+    #[inline]
+    fn heavy_barrier(&self) {
+        if self.membarrier {
+            membarrier::barrier();
+        } else {
+            fence(Ordering::SeqCst);
+        }
+    }
+
+    fn synchronize(&self) {
+        let _gp = lock(&self.gp_lock);
+        // the removals before are visible to the readers that start after
+        self.heavy_barrier();
+        let target = self.gp.fetch_add(1, Ordering::SeqCst).wrapping_add(1);
+        let readers = lock(&self.readers).clone();
+        for reader in readers.iter() {
+            let waiter = Waiter::<SpinThenYield>::new();
+            // only wait the readers that are started before the grace period
+            while {
+                let ctr = reader.ctr.load(Ordering::Acquire);
+                ctr != 0 && (ctr.wrapping_sub(target) as isize) < 0
+            } {
+                waiter.snooze();
+            }
+        }
+        // the reads of the finished readers happen before the reclamation
+        self.heavy_barrier();
+    }
+
+    // the worker that calls the callbacks after each grace period
+    fn work(&self) {
+        loop {
+            let batch = core::mem::take(&mut *lock(&self.callbacks));
+            if batch.is_empty() {
+                if self.shutdown.load(Ordering::Acquire) {
+                    return;
+                }
+                std::thread::park();
+                continue;
+            }
+            self.synchronize();
+            batch.into_iter().for_each(|f| f());
+        }
+    }
+}
+
+/// A Linux style RCU domain with grace periods
+///
+/// The threads register with the domain by [`register`], then enter the read
+/// side critical sections by [`RcuThread::read_lock`], which only stores the
+/// current grace period to the record of the thread. [`synchronize`] blocks
+/// until all the read side critical sections that are started before it
+/// finish, and [`call_rcu`] defers a callback after such a grace period
+/// without blocking, the callbacks are called by a worker thread of the
+/// domain. [`RcuDomainCell`]s created in the domain hand out plain references
+/// under a read lock.
+///
+/// The read lock needs a full fence to order the record with the reads, the
+/// domain created by [`with_membarrier`] under the `membarrier` feature moves
+/// the cost to the writers by `membarrier(2)` on Linux, so the read side needs
+/// no fences at all.
+///
+/// Never call `synchronize` inside a read side critical section of the same
+/// domain, it would wait for itself forever.
+///
+/// # Examples
+///
+/// ```
+/// use rcu_cell::RcuDomain;
+///
+/// let domain = RcuDomain::new();
+/// let cell = domain.cell(1);
+/// std::thread::scope(|s| {
+///     s.spawn(|| {
+///         let thread = domain.register();
+///         let guard = thread.read_lock();
+///         let v: &i32 = cell.read(&guard).unwrap();
+///         assert!(*v == 1 || *v == 2);
+///     });
+///     // the old value is freed after the grace period
+///     cell.write(2);
+/// });
+/// assert_eq!(cell.replace(None), Some(2));
+/// ```
+///
+/// [`register`]: RcuDomain::register
+/// [`synchronize`]: RcuDomain::synchronize
+/// [`call_rcu`]: RcuDomain::call_rcu
+/// [`with_membarrier`]: RcuDomain::with_membarrier
+pub struct RcuDomain {
+    inner: Arc<Inner>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Default for RcuDomain {
+    fn default() -> Self {
+        RcuDomain::new()
+    }
+}
+
+impl fmt::Debug for RcuDomain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RcuDomain")
+            .field("gp", &self.inner.gp.load(Ordering::Relaxed))
+            .field("membarrier", &self.inner.membarrier)
+            .finish()
+    }
+}
+
+impl Drop for RcuDomain {
+    fn drop(&mut self) {
+        self.inner.shutdown.store(true, Ordering::Release);
+        if let Some(worker) = self
+            .worker
+            .get_mut()
+            .unwrap_or_else(|e| e.into_inner())
+            .take()
+        {
+            worker.thread().unpark();
+            // the worker would finish the pending callbacks before exit, it
+            // can't join itself if the domain is dropped by a callback
+            if worker.thread().id() != std::thread::current().id() {
+                let _ = worker.join();
+            }
+        }
+        // no thread could be registered any more
+        let callbacks = core::mem::take(&mut *lock(&self.inner.callbacks));
+        callbacks.into_iter().for_each(|f| f());
+    }
+}
+
+impl RcuDomain {
+    /// create a domain whose readers issue a fence in `read_lock`
+    pub fn new() -> Self {
+        Self::with(false)
+    }
+
+    /// create a domain that uses `membarrier(2)` to order the readers, so
+    /// that `read_lock` needs no fence. It falls back to `new` if the
+    /// `membarrier` feature is not enabled or the syscall is not supported,
+    /// see [`uses_membarrier`](Self::uses_membarrier)
+    pub fn with_membarrier() -> Self {
+        Self::with(membarrier::register())
+    }
+
+    pub(crate) fn with(membarrier: bool) -> Self {
+        RcuDomain {
+            inner: Arc::new(Inner {
+                gp: CachePadded::new(AtomicUsize::new(1)),
+                membarrier,
+                readers: Mutex::new(Vec::new()),
+                gp_lock: Mutex::new(()),
+                callbacks: Mutex::new(Vec::new()),
+                shutdown: AtomicBool::new(false),
+            }),
+            worker: Mutex::new(None),
+        }
+    }
+
+    /// check if the domain uses `membarrier(2)` instead of the read side fences
+    #[inline]
+    pub fn uses_membarrier(&self) -> bool {
+        self.inner.membarrier
+    }
+
+    /// the current grace period
+    #[cfg(test)]
+    pub(crate) fn gp(&self) -> usize {
+        self.inner.gp.load(Ordering::Acquire)
+    }
+
+    /// register the current thread to the domain, it's unregistered when the
+    /// returned handle is dropped
+    pub fn register(&self) -> RcuThread<'_> {
+        let record = Arc::new(Record {
+            ctr: CachePadded::new(AtomicUsize::new(0)),
+        });
+        lock(&self.inner.readers).push(record.clone());
+        RcuThread {
+            domain: self,
+            record,
+            nesting: Cell::new(0),
+            phantom: PhantomData,
+        }
+    }
+
+    /// block until all the read side critical sections that are started
+    /// before finish, the ones that are started after are not waited
+    pub fn synchronize(&self) {
+        self.inner.synchronize();
+    }
+
+    /// call the callback after a grace period without blocking, the
+    /// callbacks are called in order by a worker thread of the domain
+    pub fn call_rcu(&self, f: RcuCallback) {
+        lock(&self.inner.callbacks).push(f);
+        let mut worker = lock(&self.worker);
+        let worker = worker.get_or_insert_with(|| {
+            let inner = self.inner.clone();
+            std::thread::Builder::new()
+                .name("rcu-callbacks".into())
+                .spawn(move || inner.work())
+                .expect("failed to spawn the rcu callback worker")
+        });
+        worker.thread().unpark();
+    }
+
+    /// block until all the callbacks that are deferred before are called
+    pub fn barrier(&self) {
+        let (tx, rx) = std::sync::mpsc::channel();
+        self.call_rcu(Box::new(move || {
+            let _ = tx.send(());
+        }));
+        let _ = rx.recv();
+    }
+
+    /// create a cell of the domain
+    pub fn cell<T>(&self, data: impl Into<Option<T>>) -> RcuDomainCell<'_, T> {
+        RcuDomainCell::new(self, data)
+    }
+}
+
+/// The registration of a thread to the [`RcuDomain`], it's bound to the
+/// thread and unregisters the thread when dropped
+pub struct RcuThread<'d> {
+    domain: &'d RcuDomain,
+    record: Arc<Record>,
+    nesting: Cell<usize>,
+    // the registration can't be sent to other threads
+    phantom: PhantomData<*const ()>,
+}
+
+impl Drop for RcuThread<'_> {
+    fn drop(&mut self) {
+        lock(&self.domain.inner.readers).retain(|r| !Arc::ptr_eq(r, &self.record));
+    }
+}
+
+impl fmt::Debug for RcuThread<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RcuThread")
+            .field("nesting", &self.nesting.get())
+            .finish()
+    }
+}
+
+impl<'d> RcuThread<'d> {
+    /// the domain that the thread is registered to
+    #[inline]
+    pub fn domain(&self) -> &'d RcuDomain {
+        self.domain
+    }
+
+    /// enter a read side critical section, the values that are read from
+    /// the cells of the domain would not be freed until the guard is
+    /// dropped. The critical sections could be nested.
+    #[inline]
+    pub fn read_lock(&self) -> RcuReadGuard<'_> {
+        let nesting = self.nesting.get();
+        if nesting == 0 {
+            let inner = &self.domain.inner;
+            // the reads can't be reordered before a new grace period
+            let gp = inner.gp.load(Ordering::Acquire);
+            self.record.ctr.store(gp, Ordering::Relaxed);
+            // order the record before the reads in the critical section
+            if inner.membarrier {
+                compiler_fence(Ordering::SeqCst);
+            } else {
+                fence(Ordering::SeqCst);
+            }
+        }
+        self.nesting.set(nesting + 1);
+        RcuReadGuard { thread: self }
+    }
+}
+
+/// The read side critical section of a [`RcuThread`]
+pub struct RcuReadGuard<'a> {
+    thread: &'a RcuThread<'a>,
+}
+
+impl Drop for RcuReadGuard<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        let nesting = self.thread.nesting.get() - 1;
+        self.thread.nesting.set(nesting);
+        if nesting == 0 {
+            compiler_fence(Ordering::SeqCst);
+            // the reads in the critical section happen before
+            self.thread.record.ctr.store(0, Ordering::Release);
+        }
+    }
+}
+
+impl fmt::Debug for RcuReadGuard<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RcuReadGuard").finish()
+    }
+}
+
+impl RcuReadGuard<'_> {
+    /// the domain of the critical section
+    #[inline]
+    pub fn domain(&self) -> &RcuDomain {
+        self.thread.domain
+    }
+}
+
+/// A cell of the [`RcuDomain`], it behaves like `RwLock<Option<Box<T>>>`
+///
+/// The value is read out as a plain reference under a read lock of the
+/// domain. The writers swap in the new value at once, the old one is freed
+/// after a grace period by `call_rcu` or returned after `synchronize`.
+pub struct RcuDomainCell<'d, T> {
+    domain: &'d RcuDomain,
+    ptr: AtomicPtr<T>,
+    phantom: PhantomData<Box<T>>,
+}
+
+unsafe impl<T: Send> Send for RcuDomainCell<'_, T> {}
+unsafe impl<T: Send + Sync> Sync for RcuDomainCell<'_, T> {}
+
+impl<T> Drop for RcuDomainCell<'_, T> {
+    fn drop(&mut self) {
+        // the references are bound to the cell, no one could still read it
+        let ptr = *self.ptr.get_mut();
+        if !ptr.is_null() {
+            drop(unsafe { Box::from_raw(ptr) });
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for RcuDomainCell<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RcuDomainCell")
+            .field("ptr", &self.ptr.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+impl<'d, T> RcuDomainCell<'d, T> {
+    /// create a cell of the domain
+    pub fn new(domain: &'d RcuDomain, data: impl Into<Option<T>>) -> Self {
+        let ptr = data
+            .into()
+            .map_or(ptr::null_mut(), |v| Box::into_raw(Box::new(v)));
+        RcuDomainCell {
+            domain,
+            ptr: AtomicPtr::new(ptr),
+            phantom: PhantomData,
+        }
+    }
+
+    /// the domain of the cell
+    #[inline]
+    pub fn domain(&self) -> &'d RcuDomain {
+        self.domain
+    }
+
+    /// check if the cell is empty
+    #[inline]
+    pub fn is_none(&self) -> bool {
+        self.ptr.load(Ordering::Relaxed).is_null()
+    }
+
+    /// read out the value under the read lock, it's valid until the guard
+    /// is dropped. Panics if the guard is not of the domain of the cell
+    #[inline]
+    pub fn read<'a>(&'a self, guard: &'a RcuReadGuard<'_>) -> Option<&'a T> {
+        assert!(
+            ptr::eq(guard.domain(), self.domain),
+            "the read guard is not of the domain of the cell"
+        );
+        // the value is not freed until the grace period after the guard
+        unsafe { self.ptr.load(Ordering::Acquire).as_ref() }
+    }
+
+    /// write a value to the cell, the old one is freed by `call_rcu`
+    pub fn write(&self, data: T)
+    where
+        T: Send + 'static,
+    {
+        let old = self.swap(Some(data));
+        if !old.is_null() {
+            let old = SendPtr(old);
+            self.domain.call_rcu(Box::new(move || {
+                let old = old;
+                drop(unsafe { Box::from_raw(old.0) });
+            }));
+        }
+    }
+
+    /// write a value to the cell and return the old one after a grace
+    /// period, it blocks in `synchronize`
+    pub fn replace(&self, data: Option<T>) -> Option<T> {
+        let old = self.swap(data);
+        if old.is_null() {
+            return None;
+        }
+        self.domain.synchronize();
+        Some(*unsafe { Box::from_raw(old) })
+    }
+
+    fn swap(&self, data: Option<T>) -> *mut T {
+        let ptr = data.map_or(ptr::null_mut(), |v| Box::into_raw(Box::new(v)));
+        self.ptr.swap(ptr, Ordering::AcqRel)
+    }
+}
+
+// the old value that is sent to the callback worker
+struct SendPtr<T>(*mut T);
+
+unsafe impl<T: Send> Send for SendPtr<T> {}
@@ -0,0 +1,232 @@
+//! A minimal epoch based reclamation for [`EpochRcuCell`](crate::EpochRcuCell)
+//!
+//! Every thread owns a participant that records the global epoch it's pinned
+//! at, pinning only writes the participant of the current thread. Retired
+//! values are tagged with the global epoch, the epoch is advanced only when
+//! all the pinned participants have seen it, so a value is freed once the
+//! epoch is advanced twice since it's retired, no reader could still see it.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cell::Cell;
+use core::ptr;
+use core::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+use crossbeam_utils::CachePadded;
+
+// the pinned flag of the participant epoch, an unpinned one is zero
+const PINNED: usize = 1;
+
+/// The epoch record of a thread, it's never freed but reused by the next
+/// thread once the owner thread exits
+struct Participant {
+    // the pinned epoch shifted by one with the `PINNED` flag
+    epoch: CachePadded<AtomicUsize>,
+    in_use: AtomicBool,
+    next: *const Participant,
+}
+
+unsafe impl Sync for Participant {}
+
+struct Global {
+    epoch: CachePadded<AtomicUsize>,
+    // the push only list of all the participants
+    participants: AtomicPtr<Participant>,
+}
+
+static GLOBAL: Global = Global {
+    epoch: CachePadded::new(AtomicUsize::new(0)),
+    participants: AtomicPtr::new(ptr::null_mut()),
+};
+
+impl Global {
+    fn participants(&self) -> impl Iterator<Item = &'static Participant> {
+        let mut next = self.participants.load(Ordering::Acquire) as *const Participant;
+        core::iter::from_fn(move || {
+            // the participants are never freed
+            let p = unsafe { next.as_ref()? };
+            next = p.next;
+            Some(p)
+        })
+    }
+
+    /// reuse an idle participant or register a new one
+    fn acquire(&'static self) -> &'static Participant {
+        let idle = self.participants().find(|p| {
+            !p.in_use.load(Ordering::Relaxed)
+                && p.in_use
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+        });
+        if let Some(p) = idle {
+            return p;
+        }
+        let p = Box::leak(Box::new(Participant {
+            epoch: CachePadded::new(AtomicUsize::new(0)),
+            in_use: AtomicBool::new(true),
+            next: ptr::null(),
+        }));
+        let mut head = self.participants.load(Ordering::Relaxed);
+        loop {
+            p.next = head;
+            match self.participants.compare_exchange_weak(
+                head,
+                p,
+                Ordering::Release,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return p,
+                Err(h) => head = h,
+            }
+        }
+    }
+
+    /// advance the global epoch if all the pinned participants have seen
+    /// it, return the latest global epoch
+    fn try_advance(&self) -> usize {
+        let epoch = self.epoch.load(Ordering::Relaxed);
+        fence(Ordering::SeqCst);
+        for p in self.participants() {
+            let e = p.epoch.load(Ordering::Relaxed);
+            if e & PINNED != 0 && e >> 1 != epoch {
+                return epoch;
+            }
+        }
+        fence(Ordering::Acquire);
+        match self.epoch.compare_exchange(
+            epoch,
+            epoch.wrapping_add(1),
+            Ordering::Release,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => epoch.wrapping_add(1),
+            Err(e) => e,
+        }
+    }
+}
+
+/// The participant of the current thread
+struct Local {
+    participant: &'static Participant,
+    // the nested pins of the thread
+    pins: Cell<usize>,
+}
+
+impl Drop for Local {
+    fn drop(&mut self) {
+        self.participant.epoch.store(0, Ordering::Release);
+        self.participant.in_use.store(false, Ordering::Release);
+    }
+}
+
+std::thread_local! {
+    static LOCAL: Local = Local {
+        participant: GLOBAL.acquire(),
+        pins: Cell::new(0),
+    };
+}
+
+fn pin_participant(p: &Participant) {
+    let epoch = GLOBAL.epoch.load(Ordering::Relaxed);
+    p.epoch.store(epoch << 1 | PINNED, Ordering::Relaxed);
+    // pairs with the fence in `try_advance`, either the collector sees the
+    // pin or we see the values that are retired before it advances
+    fence(Ordering::SeqCst);
+}
+
+/// The pin of the current thread, the values that are loaded while it's
+/// alive would not be freed until it's dropped. It's `pub` in the private
+/// module since the sealed strategy of `EpochRcuCell` names it
+pub struct Guard {
+    // the participant that is owned by the guard when the thread local
+    // one is not accessible, e.g. in the destructors of other thread locals
+    owned: Option<&'static Participant>,
+    // the guard is bound to the thread
+    phantom: core::marker::PhantomData<*const ()>,
+}
+
+/// pin the current thread, nested pins are cheap
+#[inline]
+pub(crate) fn pin() -> Guard {
+    let pinned = LOCAL.try_with(|local| {
+        let pins = local.pins.get();
+        if pins == 0 {
+            pin_participant(local.participant);
+        }
+        local.pins.set(pins + 1);
+    });
+    let owned = match pinned {
+        Ok(()) => None,
+        Err(_) => {
+            let p = GLOBAL.acquire();
+            pin_participant(p);
+            Some(p)
+        }
+    };
+    Guard {
+        owned,
+        phantom: core::marker::PhantomData,
+    }
+}
+
+impl Drop for Guard {
+    #[inline]
+    fn drop(&mut self) {
+        if let Some(p) = self.owned {
+            p.epoch.store(0, Ordering::Release);
+            p.in_use.store(false, Ordering::Release);
+            return;
+        }
+        let _ = LOCAL.try_with(|local| {
+            let pins = local.pins.get() - 1;
+            local.pins.set(pins);
+            if pins == 0 {
+                local.participant.epoch.store(0, Ordering::Release);
+            }
+        });
+    }
+}
+
+/// The retired values of a cell, tagged with the epoch they are retired at,
+/// it's named by the sealed strategy like `Guard`
+pub struct Bag<T> {
+    retired: Vec<(usize, T)>,
+}
+
+impl<T> Bag<T> {
+    #[inline]
+    pub(crate) const fn new() -> Self {
+        Bag {
+            retired: Vec::new(),
+        }
+    }
+
+    /// retire the value that is just unlinked, it's kept until no pinned
+    /// reader could see it
+    pub(crate) fn retire(&mut self, value: T) {
+        // the value is unlinked before the epoch is loaded, a reader that
+        // could still see it is pinned at this epoch or an earlier one
+        fence(Ordering::SeqCst);
+        let epoch = GLOBAL.epoch.load(Ordering::Relaxed);
+        self.retired.push((epoch, value));
+    }
+
+    /// take out the retired values that no reader could see any more, the
+    /// global epoch is advanced if possible
+    pub(crate) fn collect(&mut self) -> Vec<T> {
+        let mut expired = Vec::new();
+        if self.retired.is_empty() {
+            return expired;
+        }
+        let epoch = GLOBAL.try_advance();
+        let mut i = 0;
+        while i < self.retired.len() {
+            if epoch.wrapping_sub(self.retired[i].0) >= 2 {
+                expired.push(self.retired.swap_remove(i).1);
+            } else {
+                i += 1;
+            }
+        }
+        expired
+    }
+}
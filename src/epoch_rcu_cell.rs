@@ -0,0 +1,91 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::MutexGuard;
+
+use crate::epoch::{self, Bag};
+use crate::reclaim_rcu_cell::sealed::Strategy;
+use crate::reclaim_rcu_cell::{ReclaimGuard, ReclaimRcuCell};
+
+/// RCU cell with epoch based reclamation, it behaves like
+/// `RwLock<Option<Arc<T>>>` and has the same API shape as
+/// [`RcuCell`](crate::RcuCell).
+///
+/// The readers only pin the epoch of the current thread, they never write
+/// the shared cache lines of the cell, so reading through `read_guard` or
+/// `with` costs no atomic read-modify-write at all. The writers swap in the
+/// new value at once and retire the old one to the cell, it's dropped after
+/// all the threads that were pinned have moved on. So the writers never
+/// wait for the readers, but the retired values may live longer than
+/// expected if a thread keeps a guard for long time.
+///
+/// The writers are serialized by a lock, the `try_*` writers only fail with
+/// `Locked` since there is nothing else to wait for. The retired values are
+/// collected by the writers of the cell, and all of them are dropped with
+/// the cell.
+///
+/// # Examples
+///
+/// ```
+/// use rcu_cell::EpochRcuCell;
+///
+/// let cell = EpochRcuCell::new(1);
+/// let guard = cell.read_guard().unwrap();
+/// // the writer doesn't wait for the guard
+/// let old = cell.write(2);
+/// assert_eq!(*guard, 1);
+/// assert_eq!(old.as_deref(), Some(&1));
+/// drop(guard);
+/// assert_eq!(cell.with(|v| v.copied()), Some(2));
+/// ```
+pub type EpochRcuCell<T> = ReclaimRcuCell<T, EpochReclaim>;
+
+/// A scoped read guard of the [`EpochRcuCell`] value.
+///
+/// The guard pins the epoch of the current thread, so it can't be sent to
+/// other threads. The writers don't wait for the guard, but the values they
+/// retire are not dropped until it's dropped.
+pub type EpochGuard<'a, T> = ReclaimGuard<'a, T, EpochReclaim>;
+
+/// The epoch based reclamation of [`EpochRcuCell`], the old values are kept
+/// in the bag of the cell until no pinned thread could see them
+#[derive(Debug)]
+pub struct EpochReclaim(());
+
+impl<T> Strategy<T> for EpochReclaim {
+    const NAME: &'static str = "EpochRcuCell";
+
+    type Retired = Bag<Arc<T>>;
+
+    type Protect<'a> = epoch::Guard;
+
+    #[inline]
+    fn create() -> (Self, Bag<Arc<T>>) {
+        (EpochReclaim(()), Bag::new())
+    }
+
+    #[inline]
+    fn protect(&self, src: &AtomicPtr<T>) -> (*mut T, epoch::Guard) {
+        let pin = epoch::pin();
+        // the value can't be freed while pinned
+        (src.load(Ordering::Acquire), pin)
+    }
+
+    // retire the old value, the returned one is a new reference
+    fn reclaim(&self, bag: &mut Bag<Arc<T>>, old: Option<Arc<T>>) -> Option<Arc<T>> {
+        let ret = old.clone();
+        if let Some(old) = old {
+            bag.retire(old);
+        }
+        ret
+    }
+
+    // drop the values that are expired, the destructors are run out of the
+    // lock
+    #[inline]
+    fn unlock(mut bag: MutexGuard<'_, Bag<Arc<T>>>) {
+        let expired: Vec<Arc<T>> = bag.collect();
+        drop(bag);
+        drop(expired);
+    }
+}
@@ -0,0 +1,99 @@
+use core::fmt;
+
+/// The error returned by the non-blocking `try_*` operations and the timed
+/// operations of the cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RcuError {
+    /// the reader counter of the cell is full
+    TooManyReaders,
+    /// the writer has to wait for the active readers to release the value
+    WouldBlock,
+    /// another writer is updating the value
+    Locked,
+    /// the operation is not done before the timeout
+    Timeout,
+}
+
+impl fmt::Display for RcuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let msg = match self {
+            RcuError::TooManyReaders => "too many readers of the rcu cell",
+            RcuError::WouldBlock => "the rcu cell is being read",
+            RcuError::Locked => "the rcu cell is being updated",
+            RcuError::Timeout => "the rcu cell operation timed out",
+        };
+        f.write_str(msg)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RcuError {}
+
+/// The error returned by a failed `compare_and_set`
+///
+/// `P` is the pointer type stored in the cell, e.g. `Option<Arc<T>>`
+/// for `RcuCell<T>` and `Arc<T>` for `RcuCellNonNull<T>`
+pub struct CasFailure<P> {
+    /// the value observed in the cell when the comparison failed
+    pub current: P,
+    /// the rejected new value that was passed to `compare_and_set`
+    pub new: P,
+}
+
+impl<P: fmt::Debug> fmt::Debug for CasFailure<P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CasFailure")
+            .field("current", &self.current)
+            .field("new", &self.new)
+            .finish()
+    }
+}
+
+impl<P> fmt::Display for CasFailure<P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("the current value of the cell is not the expected one")
+    }
+}
+
+/// The error returned by a failed `try_write` or `write_timeout`, the value
+/// that is not written is given back
+///
+/// `P` is the pointer type that is written, e.g. `Arc<T>`
+pub struct WriteFailure<P> {
+    /// why the value is not written
+    pub error: RcuError,
+    /// the rejected new value
+    pub new: P,
+}
+
+impl<P> WriteFailure<P> {
+    #[inline]
+    pub(crate) fn map<Q>(self, f: impl FnOnce(P) -> Q) -> WriteFailure<Q> {
+        WriteFailure {
+            error: self.error,
+            new: f(self.new),
+        }
+    }
+}
+
+impl<P> From<WriteFailure<P>> for RcuError {
+    #[inline]
+    fn from(failure: WriteFailure<P>) -> Self {
+        failure.error
+    }
+}
+
+impl<P: fmt::Debug> fmt::Debug for WriteFailure<P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WriteFailure")
+            .field("error", &self.error)
+            .field("new", &self.new)
+            .finish()
+    }
+}
+
+impl<P> fmt::Display for WriteFailure<P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.error, f)
+    }
+}
@@ -0,0 +1,50 @@
+use core::fmt;
+use core::ops::Deref;
+
+use crate::link::ReadRef;
+
+/// A scoped read guard of the rcu cell value.
+///
+/// The guard keeps the reader count of the cell pinned until it's dropped,
+/// so reading through it doesn't touch the `Arc` ref count at all. While the
+/// guard is alive the writers of the same cell could still publish new
+/// values, but would wait for the guard before returning the old value. So
+/// don't hold it for too long time and never write to the cell in the same
+/// thread while holding the guard, that would dead lock.
+pub struct RcuGuard<'a, T: ?Sized> {
+    value: &'a T,
+    _reader: ReadRef<'a, T>,
+}
+
+impl<'a, T: ?Sized> RcuGuard<'a, T> {
+    /// # Safety
+    /// the ptr of the reader must be non-null and point to a valid `T`
+    #[inline]
+    pub(crate) unsafe fn new(reader: ReadRef<'a, T>) -> Self {
+        RcuGuard {
+            value: reader.ptr().unwrap_unchecked().as_ref(),
+            _reader: reader,
+        }
+    }
+}
+
+impl<T: ?Sized> Deref for RcuGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RcuGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.value, f)
+    }
+}
+
+impl<T: ?Sized + fmt::Display> fmt::Display for RcuGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.value, f)
+    }
+}
@@ -0,0 +1,242 @@
+//! A minimal hazard pointer reclamation for [`HazardRcuCell`](crate::HazardRcuCell)
+//!
+//! A reader publishes the pointer it loads to a hazard slot of its thread and
+//! validates that the pointer is still in the cell, so the pointer is
+//! protected once it's validated. The writers retire the unlinked pointers to
+//! the retire list of the thread, which is scanned against all the hazard
+//! slots once it's longer than twice the number of slots. Only the pointers
+//! that are protected could be kept, so the garbage is always bounded even
+//! if some readers stall.
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::ptr;
+use core::sync::atomic::{fence, AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crossbeam_utils::CachePadded;
+
+// the retire list is scanned when it's longer than this plus twice the slots
+const SCAN_SLACK: usize = 8;
+
+/// The hazard slot, it's never freed but reused by other readers once it's
+/// released by the owner thread
+struct Slot {
+    ptr: CachePadded<AtomicPtr<()>>,
+    in_use: AtomicBool,
+    next: *const Slot,
+}
+
+unsafe impl Sync for Slot {}
+
+/// The pointer that is retired, with the function to free it
+struct Retired {
+    ptr: *mut (),
+    free: unsafe fn(*mut ()),
+}
+
+unsafe impl Send for Retired {}
+
+unsafe fn free_arc<T>(ptr: *mut ()) {
+    drop(Arc::from_raw(ptr as *const T));
+}
+
+struct Global {
+    // the push only list of all the slots
+    slots: AtomicPtr<Slot>,
+    len: AtomicUsize,
+    // the retire lists of the exited threads
+    orphans: Mutex<Vec<Retired>>,
+}
+
+static GLOBAL: Global = Global {
+    slots: AtomicPtr::new(ptr::null_mut()),
+    len: AtomicUsize::new(0),
+    orphans: Mutex::new(Vec::new()),
+};
+
+impl Global {
+    fn slots(&self) -> impl Iterator<Item = &'static Slot> {
+        let mut next = self.slots.load(Ordering::Acquire) as *const Slot;
+        core::iter::from_fn(move || {
+            // the slots are never freed
+            let s = unsafe { next.as_ref()? };
+            next = s.next;
+            Some(s)
+        })
+    }
+
+    /// reuse an idle slot or allocate a new one
+    fn acquire(&self) -> &'static Slot {
+        let idle = self.slots().find(|s| {
+            !s.in_use.load(Ordering::Relaxed)
+                && s.in_use
+                    .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                    .is_ok()
+        });
+        if let Some(s) = idle {
+            return s;
+        }
+        let s = Box::leak(Box::new(Slot {
+            ptr: CachePadded::new(AtomicPtr::new(ptr::null_mut())),
+            in_use: AtomicBool::new(true),
+            next: ptr::null(),
+        }));
+        let mut head = self.slots.load(Ordering::Relaxed);
+        loop {
+            s.next = head;
+            match self
+                .slots
+                .compare_exchange_weak(head, s, Ordering::Release, Ordering::Relaxed)
+            {
+                Ok(_) => break,
+                Err(h) => head = h,
+            }
+        }
+        self.len.fetch_add(1, Ordering::Relaxed);
+        s
+    }
+
+    /// free the retired pointers that are not protected by any slot, the
+    /// protected ones are kept in the list
+    fn scan(&self, retired: &mut Vec<Retired>) {
+        if let Ok(mut orphans) = self.orphans.try_lock() {
+            retired.append(&mut orphans);
+        }
+        // pairs with the fence of the readers, either the reader sees the
+        // pointer unlinked or we see its hazard
+        fence(Ordering::SeqCst);
+        let mut hazards: Vec<*mut ()> = self
+            .slots()
+            .map(|s| s.ptr.load(Ordering::Relaxed))
+            .filter(|p| !p.is_null())
+            .collect();
+        hazards.sort_unstable();
+        fence(Ordering::Acquire);
+        let mut expired = Vec::new();
+        let mut i = 0;
+        while i < retired.len() {
+            if hazards.binary_search(&retired[i].ptr).is_err() {
+                expired.push(retired.swap_remove(i));
+            } else {
+                i += 1;
+            }
+        }
+        // the destructors may retire pointers again
+        for r in expired {
+            unsafe { (r.free)(r.ptr) };
+        }
+    }
+}
+
+/// The hazard slots and the retire list of the current thread
+struct Local {
+    // the idle slots owned by the thread
+    slots: RefCell<Vec<&'static Slot>>,
+    retired: RefCell<Vec<Retired>>,
+}
+
+impl Drop for Local {
+    fn drop(&mut self) {
+        for s in self.slots.get_mut().drain(..) {
+            s.in_use.store(false, Ordering::Release);
+        }
+        let retired = core::mem::take(self.retired.get_mut());
+        if !retired.is_empty() {
+            let mut orphans = GLOBAL.orphans.lock().unwrap_or_else(|e| e.into_inner());
+            orphans.extend(retired);
+        }
+    }
+}
+
+std::thread_local! {
+    static LOCAL: Local = const {
+        Local {
+            slots: RefCell::new(Vec::new()),
+            retired: RefCell::new(Vec::new()),
+        }
+    };
+}
+
+/// A hazard slot that protects a pointer, the slot is cleared and returned
+/// to the thread when dropped. It's `pub` in the private module since the
+/// sealed strategy of `HazardRcuCell` names it
+pub struct Hazard {
+    slot: &'static Slot,
+    // the hazard is bound to the thread that owns the slot
+    phantom: core::marker::PhantomData<*const ()>,
+}
+
+impl Hazard {
+    /// take a hazard slot of the current thread
+    #[inline]
+    pub(crate) fn new() -> Self {
+        let slot = LOCAL
+            .try_with(|local| local.slots.borrow_mut().pop())
+            .ok()
+            .flatten()
+            .unwrap_or_else(|| GLOBAL.acquire());
+        Hazard {
+            slot,
+            phantom: core::marker::PhantomData,
+        }
+    }
+
+    /// load the pointer from the source and protect it, the returned pointer
+    /// would not be freed until the hazard is dropped or reused
+    #[inline]
+    pub(crate) fn protect<T>(&self, src: &AtomicPtr<T>) -> *mut T {
+        let mut ptr = src.load(Ordering::Relaxed);
+        loop {
+            self.slot.ptr.store(ptr as *mut (), Ordering::Relaxed);
+            // pairs with the fence in `scan`
+            fence(Ordering::SeqCst);
+            // the pointer is protected if it's not unlinked yet
+            let cur = src.load(Ordering::Acquire);
+            if cur == ptr {
+                return ptr;
+            }
+            ptr = cur;
+        }
+    }
+}
+
+impl Drop for Hazard {
+    #[inline]
+    fn drop(&mut self) {
+        self.slot.ptr.store(ptr::null_mut(), Ordering::Release);
+        let slot = self.slot;
+        if LOCAL
+            .try_with(|local| local.slots.borrow_mut().push(slot))
+            .is_err()
+        {
+            slot.in_use.store(false, Ordering::Release);
+        }
+    }
+}
+
+/// retire the Arc that is just unlinked, it's dropped once no hazard
+/// protects it
+pub(crate) fn retire<T: Send + Sync + 'static>(data: Arc<T>) {
+    let mut retired = Some(Retired {
+        ptr: Arc::into_raw(data) as *mut (),
+        free: free_arc::<T>,
+    });
+    let _ = LOCAL.try_with(|local| {
+        let mut list = local.retired.take();
+        list.extend(retired.take());
+        if list.len() > 2 * GLOBAL.len.load(Ordering::Relaxed) + SCAN_SLACK {
+            GLOBAL.scan(&mut list);
+        }
+        // the destructors that run in the scan may have retired some more
+        list.append(&mut local.retired.borrow_mut());
+        *local.retired.borrow_mut() = list;
+    });
+    if let Some(retired) = retired {
+        // the thread is exiting, leave it to other threads
+        let mut orphans = GLOBAL.orphans.lock().unwrap_or_else(|e| e.into_inner());
+        orphans.push(retired);
+    }
+}
@@ -0,0 +1,79 @@
+use alloc::sync::Arc;
+use core::sync::atomic::AtomicPtr;
+
+use crate::hazard::{self, Hazard};
+use crate::reclaim_rcu_cell::sealed::Strategy;
+use crate::reclaim_rcu_cell::{ReclaimGuard, ReclaimRcuCell};
+
+/// RCU cell protected by hazard pointers, it behaves like
+/// `RwLock<Option<Arc<T>>>` and has the same read/write/update/take surface
+/// as [`RcuCell`](crate::RcuCell).
+///
+/// A reader publishes the pointer it loads to a hazard slot of its thread and
+/// validates it against the cell, it never writes the cache lines of the
+/// cell. The writers swap in the new value at once and retire the old one to
+/// the retire list of the thread, which is scanned against all the hazard
+/// slots when it grows. Unlike [`EpochRcuCell`](crate::EpochRcuCell), a
+/// stalled reader only keeps the values that it protects, so the garbage is
+/// bounded by the number of the threads and the hazard slots.
+///
+/// The writers are serialized by a lock, the `try_*` writers only fail with
+/// `Locked`. Since the retired values may be dropped by other threads after
+/// the cell is dropped, the cell requires `T: Send + Sync + 'static`.
+///
+/// # Examples
+///
+/// ```
+/// use rcu_cell::HazardRcuCell;
+///
+/// let cell = HazardRcuCell::new(1);
+/// let guard = cell.read_guard().unwrap();
+/// // the writer doesn't wait for the guard
+/// let old = cell.update(|v| v.map(|v| *v + 1));
+/// assert_eq!(*guard, 1);
+/// assert_eq!(old.as_deref(), Some(&1));
+/// drop(guard);
+/// assert_eq!(cell.take().as_deref(), Some(&2));
+/// ```
+pub type HazardRcuCell<T> = ReclaimRcuCell<T, HazardReclaim>;
+
+/// A scoped read guard of the [`HazardRcuCell`] value.
+///
+/// The guard holds a hazard slot of the current thread, so it can't be sent
+/// to other threads. The writers don't wait for the guard, but the value is
+/// not dropped until the guard is dropped.
+pub type HazardGuard<'a, T> = ReclaimGuard<'a, T, HazardReclaim>;
+
+/// The hazard pointer reclamation of [`HazardRcuCell`], the old values are
+/// retired to the list of the writer thread until no hazard protects them
+#[derive(Debug)]
+pub struct HazardReclaim(());
+
+impl<T: Send + Sync + 'static> Strategy<T> for HazardReclaim {
+    const NAME: &'static str = "HazardRcuCell";
+
+    type Retired = ();
+
+    type Protect<'a> = Hazard;
+
+    #[inline]
+    fn create() -> (Self, ()) {
+        (HazardReclaim(()), ())
+    }
+
+    #[inline]
+    fn protect(&self, src: &AtomicPtr<T>) -> (*mut T, Hazard) {
+        let hazard = Hazard::new();
+        // the value can't be freed while it's protected
+        (hazard.protect(src), hazard)
+    }
+
+    // retire the old value, the returned one is a new reference
+    fn reclaim(&self, _: &mut (), old: Option<Arc<T>>) -> Option<Arc<T>> {
+        let ret = old.clone();
+        if let Some(old) = old {
+            hazard::retire(old);
+        }
+        ret
+    }
+}
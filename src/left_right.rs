@@ -0,0 +1,297 @@
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::Deref;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use crossbeam_utils::CachePadded;
+
+use crate::park::Waiter;
+use crate::strategy::SpinThenYield;
+
+/// The operation log of a [`LeftRight`] is absorbed by both copies of the
+/// data, once when it's appended and once after it's published.
+///
+/// The two applications must leave the copies the same, so the operation
+/// must be deterministic.
+///
+/// # Examples
+///
+/// ```
+/// use rcu_cell::Absorb;
+/// use std::collections::HashMap;
+///
+/// enum Op {
+///     Insert(u32, String),
+///     Remove(u32),
+/// }
+///
+/// struct Index(HashMap<u32, String>);
+///
+/// impl Absorb<Op> for Index {
+///     fn absorb_first(&mut self, op: &mut Op) {
+///         match op {
+///             Op::Insert(k, v) => self.0.insert(*k, v.clone()),
+///             Op::Remove(k) => self.0.remove(k),
+///         };
+///     }
+///
+///     // the last application could move the value into the copy
+///     fn absorb_second(&mut self, op: Op) {
+///         match op {
+///             Op::Insert(k, v) => self.0.insert(k, v),
+///             Op::Remove(k) => self.0.remove(&k),
+///         };
+///     }
+/// }
+/// ```
+pub trait Absorb<O> {
+    /// apply the operation to the inactive copy when it's appended
+    fn absorb_first(&mut self, op: &mut O);
+
+    /// apply the operation to the other copy after it's published, this is
+    /// the last time that the operation is used
+    fn absorb_second(&mut self, mut op: O) {
+        self.absorb_first(&mut op)
+    }
+}
+
+// the copies may differ by more than the log after a panic in the writer,
+// so the writers never go on with a poisoned log
+#[inline]
+fn lock<T>(m: &Mutex<T>) -> MutexGuard<'_, T> {
+    m.lock()
+        .expect("LeftRight is poisoned by a panic in a writer")
+}
+
+/// A left-right cell that keeps two copies of the data
+///
+/// The readers always read the active copy and get a `&T` without any
+/// retry, so the reads are wait free. The writer appends operations to a
+/// log, each one is applied to the inactive copy in place at once, and
+/// `publish` flips the copies so that the new readers see the changes. The
+/// operations are then replayed on the old copy after its readers are gone,
+/// so the data is never cloned after creation. It complements
+/// [`RcuCell`](crate::RcuCell) for the large data that is updated
+/// incrementally, at the cost of keeping two copies.
+///
+/// The writers are serialized by a lock. `publish` waits the readers of the
+/// old copy, so never publish in the same thread while holding a guard,
+/// that would dead lock.
+///
+/// # Panics
+///
+/// A panic in `absorb_first`, `absorb_second` or the iterator of `extend`
+/// may leave a copy half applied, so the lock is poisoned and all the later
+/// `append`, `extend` and `publish` panic. The readers still read the last
+/// published copy, which is never touched by the panic.
+///
+/// # Examples
+///
+/// ```
+/// use rcu_cell::{Absorb, LeftRight};
+///
+/// struct Push(u32);
+///
+/// impl Absorb<Push> for Vec<u32> {
+///     fn absorb_first(&mut self, op: &mut Push) {
+///         self.push(op.0);
+///     }
+/// }
+///
+/// let lr = LeftRight::new(vec![1]);
+/// lr.append(Push(2));
+/// // the operation is not visible until it's published
+/// assert_eq!(*lr.read(), [1]);
+/// lr.publish();
+/// assert_eq!(*lr.read(), [1, 2]);
+/// ```
+pub struct LeftRight<T, O> {
+    copies: [UnsafeCell<T>; 2],
+    // the copy that the readers read
+    active: CachePadded<AtomicUsize>,
+    // the indicator that the new readers arrive at
+    version: CachePadded<AtomicUsize>,
+    indicators: [CachePadded<AtomicUsize>; 2],
+    // the operations that are not replayed on the active copy yet, the lock
+    // also serializes the writers
+    log: Mutex<Vec<O>>,
+}
+
+unsafe impl<T: Send, O: Send> Send for LeftRight<T, O> {}
+unsafe impl<T: Send + Sync, O: Send> Sync for LeftRight<T, O> {}
+
+impl<T: Default + Clone, O> Default for LeftRight<T, O> {
+    fn default() -> Self {
+        LeftRight::new(T::default())
+    }
+}
+
+impl<T: fmt::Debug, O> fmt::Debug for LeftRight<T, O> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LeftRight")
+            .field("value", &*self.read())
+            .finish()
+    }
+}
+
+impl<T, O> LeftRight<T, O> {
+    /// create a left-right cell from the value, the second copy is cloned
+    /// from it
+    pub fn new(value: T) -> Self
+    where
+        T: Clone,
+    {
+        Self::from_copies(value.clone(), value)
+    }
+
+    /// create a left-right cell from two copies of the data, the copies must
+    /// be the same
+    pub fn from_copies(left: T, right: T) -> Self {
+        LeftRight {
+            copies: [UnsafeCell::new(left), UnsafeCell::new(right)],
+            active: CachePadded::new(AtomicUsize::new(0)),
+            version: CachePadded::new(AtomicUsize::new(0)),
+            indicators: [
+                CachePadded::new(AtomicUsize::new(0)),
+                CachePadded::new(AtomicUsize::new(0)),
+            ],
+            log: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// read the active copy, the guard never blocks and is never blocked
+    /// by the writer, but `publish` would wait until it's dropped
+    #[inline]
+    pub fn read(&self) -> LeftRightGuard<'_, T> {
+        use Ordering::SeqCst;
+        let indicator = &*self.indicators[self.version.load(SeqCst)];
+        // pairs with the SeqCst operations of `publish`, either the writer
+        // sees the reader or the reader sees the flipped copy
+        indicator.fetch_add(1, SeqCst);
+        let active = self.active.load(SeqCst);
+        LeftRightGuard {
+            // the active copy is never written until the reader departs
+            value: unsafe { &*self.copies[active].get() },
+            indicator,
+        }
+    }
+
+    /// call the closure with a reference of the active copy, this is the
+    /// closure form of `read`
+    #[inline]
+    pub fn with<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        f(&self.read())
+    }
+
+    /// the number of the operations that are appended but not published
+    #[inline]
+    pub fn pending(&self) -> usize {
+        self.log.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// convert to the active copy, the pending operations are dropped
+    pub fn into_inner(self) -> T {
+        let active = self.active.load(Ordering::Relaxed);
+        let [left, right] = self.copies;
+        if active == 0 {
+            left.into_inner()
+        } else {
+            right.into_inner()
+        }
+    }
+
+    // wait the readers that arrived at the indicator to depart
+    fn drain(&self, version: usize) {
+        let waiter = Waiter::<SpinThenYield>::new();
+        while self.indicators[version].load(Ordering::SeqCst) != 0 {
+            waiter.snooze();
+        }
+    }
+}
+
+impl<T: Absorb<O>, O> LeftRight<T, O> {
+    /// append an operation to the log and apply it to the inactive copy,
+    /// it's visible to the readers after `publish`
+    pub fn append(&self, mut op: O) {
+        let mut log = lock(&self.log);
+        // the active copy is only flipped under the lock
+        let inactive = self.active.load(Ordering::Relaxed) ^ 1;
+        // no reader could see the inactive copy
+        unsafe { (*self.copies[inactive].get()).absorb_first(&mut op) };
+        log.push(op);
+    }
+
+    /// append the operations, like calling `append` for each of them
+    pub fn extend(&self, ops: impl IntoIterator<Item = O>) {
+        let mut log = lock(&self.log);
+        let inactive = self.active.load(Ordering::Relaxed) ^ 1;
+        let copy = unsafe { &mut *self.copies[inactive].get() };
+        for mut op in ops {
+            copy.absorb_first(&mut op);
+            log.push(op);
+        }
+    }
+
+    /// publish the appended operations by flipping the copies, then wait the
+    /// readers of the old copy and replay the operations on it
+    pub fn publish(&self) {
+        use Ordering::*;
+        let mut log = lock(&self.log);
+        if log.is_empty() {
+            return;
+        }
+        let old = self.active.load(Relaxed);
+        self.active.store(old ^ 1, SeqCst);
+        // the readers may still read the old copy, wait the ones that
+        // arrived at both indicators, flipping the indicator in between so
+        // that the new readers could never starve the writer
+        let version = self.version.load(Relaxed);
+        self.drain(version ^ 1);
+        self.version.store(version ^ 1, SeqCst);
+        self.drain(version);
+        // no reader could see the old copy now
+        let copy = unsafe { &mut *self.copies[old].get() };
+        for op in log.drain(..) {
+            copy.absorb_second(op);
+        }
+    }
+}
+
+/// A read guard of the active copy of [`LeftRight`]
+pub struct LeftRightGuard<'a, T> {
+    value: &'a T,
+    indicator: &'a AtomicUsize,
+}
+
+impl<T> Drop for LeftRightGuard<'_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.indicator.fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl<T> Deref for LeftRightGuard<'_, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for LeftRightGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.value, f)
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for LeftRightGuard<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.value, f)
+    }
+}
@@ -3,18 +3,75 @@
 
 extern crate alloc;
 
+mod cache;
+#[cfg(feature = "std")]
+mod domain;
+#[cfg(feature = "std")]
+mod epoch;
+#[cfg(feature = "std")]
+mod epoch_rcu_cell;
+mod error;
+mod guard;
+#[cfg(feature = "std")]
+mod hazard;
+#[cfg(feature = "std")]
+mod hazard_rcu_cell;
+#[cfg(feature = "std")]
+mod left_right;
 mod link;
+#[cfg(feature = "std")]
+mod membarrier;
+mod notify;
+mod park;
+mod pointer;
 mod rcu_cell;
 mod rcu_cell_nonnull;
+mod rcu_cell_of;
+mod rcu_value;
 mod rcu_weak;
+#[cfg(feature = "std")]
+mod reclaim_rcu_cell;
+mod strategy;
+#[cfg(feature = "std")]
+mod striped_rcu_cell;
+mod subscriber;
+#[cfg(feature = "std")]
+mod wait;
 
+pub use cache::RcuCache;
+#[cfg(feature = "std")]
+pub use domain::{RcuCallback, RcuDomain, RcuDomainCell, RcuReadGuard, RcuThread};
+#[cfg(feature = "std")]
+pub use epoch_rcu_cell::{EpochGuard, EpochRcuCell, EpochReclaim};
+pub use error::{CasFailure, RcuError, WriteFailure};
+pub use guard::RcuGuard;
+#[cfg(feature = "std")]
+pub use hazard_rcu_cell::{HazardGuard, HazardRcuCell, HazardReclaim};
+#[cfg(feature = "std")]
+pub use left_right::{Absorb, LeftRight, LeftRightGuard};
+pub use pointer::RcuPointer;
 pub use rcu_cell::RcuCell;
 pub use rcu_cell_nonnull::RcuCellNonNull;
+pub use rcu_cell_of::RcuCellOf;
+pub use rcu_value::{NoUninit, RcuValue};
 pub use rcu_weak::RcuWeak;
+#[cfg(feature = "std")]
+pub use reclaim_rcu_cell::{Reclaim, ReclaimGuard, ReclaimRcuCell};
+#[cfg(feature = "std")]
+pub use strategy::SpinThenYield;
+pub use strategy::{Spin, SpinThenPark, WaitStrategy};
+#[cfg(feature = "std")]
+pub use striped_rcu_cell::{StripedGuard, StripedRcuCell, StripedReclaim};
+pub use subscriber::Subscriber;
 
-// we only support 64-bit platform
-const _: () = assert!(usize::MAX.count_ones() == 64);
-const _: () = assert!(core::mem::size_of::<*const ()>() == 8);
+// we only support 32-bit and 64-bit platform, the 32-bit platform
+// needs 64-bit atomics to pack the pointer and the reader count
+#[cfg(not(any(
+    target_pointer_width = "64",
+    all(target_pointer_width = "32", target_has_atomic = "64")
+)))]
+compile_error!("rcu_cell only supports 64-bit platforms and 32-bit platforms with 64-bit atomics");
+const _: () = assert!(core::mem::size_of::<*const ()>() == core::mem::size_of::<usize>());
 
 use alloc::sync::Arc;
 
@@ -63,9 +120,85 @@ impl<T> ArcPointer<T> for Arc<T> {
 #[cfg(test)]
 mod test {
     use super::RcuCell;
+    use alloc::string::String;
     use alloc::sync::Arc;
     use core::sync::atomic::{AtomicUsize, Ordering};
 
+    /// a value that counts its live instances, each test has its own counter
+    /// since the tests run in parallel
+    #[derive(Debug)]
+    struct Live(usize, &'static AtomicUsize);
+
+    impl Live {
+        fn new(data: usize, live: &'static AtomicUsize) -> Self {
+            live.fetch_add(1, Ordering::Relaxed);
+            Live(data, live)
+        }
+    }
+
+    impl Drop for Live {
+        fn drop(&mut self) {
+            self.1.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// retry until `done` returns true, fail if it takes too long, e.g. the
+    /// retired values that other tests keep pinned are never collected
+    #[cfg(feature = "std")]
+    fn eventually(what: &str, mut done: impl FnMut() -> bool) {
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(10);
+        while !done() {
+            assert!(std::time::Instant::now() < deadline, "timed out: {what}");
+            std::thread::yield_now();
+        }
+    }
+
+    /// the writers and the readers that are shared by the cells of all the
+    /// reclamation strategies, `t` holds a value that is newer than `foo(0)`
+    #[cfg(feature = "std")]
+    fn check_reclaim_cell<R>(t: super::ReclaimRcuCell<Live, R>, foo: fn(usize) -> Live)
+    where
+        R: super::Reclaim<Live> + super::Reclaim<usize>,
+        super::ReclaimRcuCell<usize, R>: Sync,
+    {
+        use super::RcuError;
+
+        let old = t.update(|v| v.map(|v| foo(v.0 + 1))).unwrap();
+        assert_eq!(t.read().map(|v| v.0), Some(old.0 + 1));
+        let cur = t.read();
+        assert!(t.arc_eq(cur.as_ref().unwrap()));
+        assert!(t.compare_and_set(cur.as_ref(), None).is_ok());
+        assert!(t.is_none());
+        let err = t.compare_and_set(cur.as_ref(), None).unwrap_err();
+        assert!(err.current.is_none());
+        drop((old, cur, err));
+        let old = t.fetch_update(|v| Some(v.map_or(Some(foo(1)), |_| None)));
+        assert!(old.unwrap().is_none());
+
+        t.update(|v| {
+            // the lock is held by the update
+            assert_eq!(t.try_write(foo(0)).unwrap_err().error, RcuError::Locked);
+            v
+        });
+        assert_eq!(t.try_take().unwrap().map(|v| v.0), Some(1));
+        assert!(t.try_write(foo(1)).unwrap().is_none());
+        drop(t);
+
+        let t = super::ReclaimRcuCell::<usize, R>::new(0);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        t.update(|v| v.map(|v| *v + 1));
+                        let v = t.read_guard().unwrap();
+                        assert!(*v > 0);
+                    }
+                });
+            }
+        });
+        assert_eq!(t.read().map(|v| *v), Some(400));
+    }
+
     #[test]
     fn test_default() {
         let x = RcuCell::<u32>::default();
@@ -223,6 +356,1357 @@ mod test {
         assert_eq!(a.read().map(|v| *v), Some(5678));
     }
 
+    #[test]
+    fn test_compare_and_set() {
+        let a = RcuCell::new(1234);
+        let curr = a.read();
+        let new = Some(Arc::new(5678));
+        let old = a.compare_and_set(curr.as_ref(), new.clone()).unwrap();
+        assert_eq!(old, curr);
+        assert!(a.arc_eq(new.as_ref().unwrap()));
+
+        // the current value is not the expected one
+        let err = a.compare_and_set(curr.as_ref(), None).unwrap_err();
+        assert_eq!(err.current, new);
+        assert!(err.new.is_none());
+        assert_eq!(a.read(), new);
+
+        let old = a.compare_and_set(new.as_ref(), None).unwrap();
+        assert_eq!(old, new);
+        assert!(a.is_none());
+        let old = a.compare_and_set(None, curr.clone()).unwrap();
+        assert!(old.is_none());
+        assert_eq!(a.read(), curr);
+
+        let b = super::RcuCellNonNull::new(1);
+        let curr = b.read();
+        let err = b.compare_and_set(&Arc::new(1), Arc::new(2)).unwrap_err();
+        assert!(Arc::ptr_eq(&err.current, &curr));
+        assert_eq!(*err.new, 2);
+        let old = b.compare_and_set(&curr, Arc::new(3)).unwrap();
+        assert!(Arc::ptr_eq(&old, &curr));
+        assert_eq!(*b.read(), 3);
+    }
+
+    #[test]
+    fn test_fetch_update() {
+        let a = RcuCell::new(1);
+        let old = a.fetch_update(|v| Some(v.map(|x| **x + 1))).unwrap();
+        assert_eq!(old.map(|v| *v), Some(1));
+        let old = a.fetch_update(|_| Some(None::<i32>)).unwrap();
+        assert_eq!(old.map(|v| *v), Some(2));
+        assert!(a.is_none());
+        let cur = a.fetch_update(|_| None::<Option<i32>>).unwrap_err();
+        assert!(cur.is_none());
+
+        let b = super::RcuCellNonNull::new(1);
+        let old = b.fetch_update(|v| (**v < 2).then(|| **v + 1)).unwrap();
+        assert_eq!(*old, 1);
+        let cur = b.fetch_update(|v| (**v < 2).then(|| **v + 1)).unwrap_err();
+        assert_eq!(*cur, 2);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_fetch_update_concurrent() {
+        let a = super::RcuCellNonNull::new(0usize);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        a.fetch_update(|v| Some(**v + 1)).unwrap();
+                        let _ = a.read();
+                    }
+                });
+            }
+        });
+        assert_eq!(*a.read(), 4000);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_credited_read() {
+        static LIVE: AtomicUsize = AtomicUsize::new(0);
+        let foo = |data| Live::new(data, &LIVE);
+
+        let t = RcuCell::new(foo(0));
+        // run out of the credits for several times
+        let reads: Vec<_> = (0..1000).map(|_| t.read().unwrap()).collect();
+        let v = t.write(foo(1)).unwrap();
+        // the unused credits are refunded by the writer
+        assert_eq!(Arc::strong_count(&v), 1001);
+        drop((reads, v));
+        assert_eq!(LIVE.load(Ordering::Relaxed), 1);
+        let v = t.read().unwrap();
+        drop(t);
+        assert_eq!(Arc::strong_count(&v), 1);
+        drop(v);
+        assert_eq!(LIVE.load(Ordering::Relaxed), 0);
+
+        let t = RcuCell::new(foo(0));
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut last = 0;
+                    for _ in 0..10000 {
+                        let v = t.read().unwrap();
+                        assert!(v.0 >= last);
+                        last = v.0;
+                    }
+                });
+            }
+            s.spawn(|| {
+                for i in 1..=1000 {
+                    t.write(foo(i));
+                }
+            });
+        });
+        assert_eq!(t.read().map(|v| v.0), Some(1000));
+        drop(t);
+        assert_eq!(LIVE.load(Ordering::Relaxed), 0);
+
+        // many readers race past the batch, only one refills at a time
+        let t = RcuCell::new(foo(0));
+        std::thread::scope(|s| {
+            for _ in 0..64 {
+                s.spawn(|| {
+                    for _ in 0..2000 {
+                        drop(t.read().unwrap());
+                    }
+                });
+            }
+        });
+        let v = t.read().unwrap();
+        let old = t.write(foo(1)).unwrap();
+        // no credit is lost or paid twice
+        assert_eq!(Arc::strong_count(&v), 2);
+        drop((v, old, t));
+        assert_eq!(LIVE.load(Ordering::Relaxed), 0);
+
+        // the fat pointers are never credited
+        let t: RcuCell<str> = RcuCell::from(Arc::from("hello"));
+        let v = t.read().unwrap();
+        assert_eq!(Arc::strong_count(&v), 2);
+        assert_eq!(&*v, "hello");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_modify() {
+        let addr = |t: &RcuCell<Vec<usize>>| t.with(|v| v.map(|v| v as *const _));
+        let t = RcuCell::new(vec![0]);
+        let p = addr(&t);
+        let version = t.version();
+        assert_eq!(t.modify(|v| v.push(1)), Some(()));
+        // the value is not shared, it's mutated in place
+        assert_eq!(addr(&t), p);
+        assert_eq!(t.version(), version + 1);
+        // the credits of the dropped readers are taken back
+        drop((0..100).map(|_| t.read()).collect::<Vec<_>>());
+        t.modify(|v| v.push(2));
+        assert_eq!(addr(&t), p);
+
+        // the value is cloned if it's shared
+        let v = t.read().unwrap();
+        t.modify(|v| v.push(3));
+        assert_ne!(addr(&t), p);
+        assert_eq!(*v, [0, 1, 2]);
+        assert_eq!(Arc::strong_count(&v), 1);
+        let w = Arc::downgrade(&t.read().unwrap());
+        let p = addr(&t);
+        t.modify(|v| v.push(4));
+        assert_ne!(addr(&t), p);
+        // the weak one is not upgraded to the new value
+        assert!(w.upgrade().is_none());
+        assert_eq!(t.read().as_deref(), Some(&vec![0, 1, 2, 3, 4]));
+
+        // the lock is released if the closure panics
+        let ret = std::panic::catch_unwind(|| t.modify(|_| panic!("modify")));
+        assert!(ret.is_err());
+        assert_eq!(t.modify(|v| v.len()), Some(5));
+        let t = RcuCell::<Vec<usize>>::none();
+        assert_eq!(t.modify(|_| unreachable!()), None::<()>);
+
+        let t = super::RcuCellNonNull::new([0usize; 2]);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        let v = t.read();
+                        assert_eq!(v[0], v[1]);
+                        t.with(|v| assert_eq!(v[0], v[1]));
+                    }
+                });
+            }
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    t.modify(|v| {
+                        v[0] += 1;
+                        v[1] += 1;
+                    });
+                }
+            });
+        });
+        assert_eq!(*t.read(), [1000, 1000]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_spilled_readers() {
+        use std::sync::Barrier;
+
+        let t = RcuCell::new([0usize; 2]);
+        let barrier = Barrier::new(9);
+        std::thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for i in 0..50 {
+                        let guards: Vec<_> = (0..200).map(|_| t.read_guard().unwrap()).collect();
+                        assert!(guards.iter().all(|v| v[0] == v[1]));
+                        if i == 0 {
+                            barrier.wait();
+                            barrier.wait();
+                        }
+                    }
+                });
+            }
+            barrier.wait();
+            // the inline counter only holds 512 readers on 64-bit platforms,
+            // far beyond it the readers that race past the threshold never
+            // overflow the counter
+            #[cfg(target_pointer_width = "64")]
+            assert_eq!(t.link.spilled_refs(), 1600 - 512);
+            barrier.wait();
+            // the readers keep crossing the spill threshold while the value
+            // is modified in place or cloned
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    t.modify(|v| {
+                        v[0] += 1;
+                        v[1] += 1;
+                    });
+                }
+            });
+        });
+        assert_eq!(t.link.spilled_refs(), 0);
+        assert_eq!(t.read().as_deref(), Some(&[1000, 1000]));
+        let p = t.with(|v| v.map(|v| v as *const _));
+        t.modify(|v| v[0] += 1);
+        // no reader is left behind
+        assert_eq!(t.with(|v| v.map(|v| v as *const _)), p);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_rcu_value() {
+        use super::RcuValue;
+
+        let v = RcuValue::new([0.0f64, 0.0, 0.5]);
+        assert_eq!(v.load(), [0.0, 0.0, 0.5]);
+        v.store([1.0, 1.0, 1.5]);
+        assert_eq!(v.swap([2.0, 2.0, 2.5]), [1.0, 1.0, 1.5]);
+        assert_eq!(v.update(|[a, b, c]| [a + 1.0, b + 1.0, c]), [2.0, 2.0, 2.5]);
+        assert_eq!(
+            v.compare_exchange([0.0, 0.0, 0.0], [9.0, 9.0, 9.0]),
+            Err([3.0, 3.0, 2.5])
+        );
+        assert_eq!(
+            v.compare_exchange([3.0, 3.0, 2.5], [4.0, 4.0, 4.5]),
+            Ok([3.0, 3.0, 2.5])
+        );
+        // the failed compare exchange doesn't bump the version
+        assert_eq!(v.load_versioned(), ([4.0, 4.0, 4.5], 4));
+        assert_eq!(v.version(), 4);
+        assert_eq!(v.into_inner(), [4.0, 4.0, 4.5]);
+
+        let v = RcuValue::new([0u64; 2]);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        v.update(|[a, b]| [a + 1, b + 1]);
+                        // the readers never see a torn value
+                        let [a, b] = v.load();
+                        assert_eq!(a, b);
+                    }
+                });
+            }
+        });
+        assert_eq!(v.load_versioned(), ([4000, 4000], 4000));
+
+        // the values that are not aligned to a word are copied by bytes
+        let v = RcuValue::new([0u16; 3]);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        v.update(|[a, b, c]| [a + 1, b + 1, c + 1]);
+                        let [a, b, c] = v.load();
+                        assert!(a == b && b == c);
+                    }
+                });
+            }
+        });
+        assert_eq!(v.load(), [4000; 3]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_left_right() {
+        use super::{Absorb, LeftRight};
+
+        #[derive(Debug, PartialEq)]
+        enum Op {
+            Push(usize),
+            Clear,
+        }
+        impl Absorb<Op> for Vec<usize> {
+            fn absorb_first(&mut self, op: &mut Op) {
+                match op {
+                    Op::Push(v) => self.push(*v),
+                    Op::Clear => self.clear(),
+                }
+            }
+        }
+
+        let lr = LeftRight::new(vec![0]);
+        lr.append(Op::Push(1));
+        lr.extend([Op::Push(2), Op::Push(3)]);
+        assert_eq!(lr.pending(), 3);
+        assert_eq!(*lr.read(), [0]);
+        lr.publish();
+        assert_eq!(*lr.read(), [0, 1, 2, 3]);
+        assert_eq!(lr.pending(), 0);
+        lr.append(Op::Clear);
+        lr.publish();
+        assert!(lr.with(|v| v.is_empty()));
+        // both copies have absorbed all the operations
+        lr.append(Op::Push(4));
+        lr.publish();
+        assert_eq!(lr.into_inner(), [4]);
+
+        let lr = LeftRight::new(Vec::new());
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut len = 0;
+                    for _ in 0..1000 {
+                        let v = lr.read();
+                        // every published batch has a whole number of items
+                        assert_eq!(v.len() % 10, 0);
+                        assert!(v.len() >= len);
+                        assert!(v.iter().enumerate().all(|(i, x)| i == *x));
+                        len = v.len();
+                    }
+                });
+            }
+            s.spawn(|| {
+                for i in 0..100 {
+                    lr.extend((i * 10..i * 10 + 10).map(Op::Push));
+                    lr.publish();
+                }
+            });
+        });
+        assert_eq!(lr.read().len(), 1000);
+        lr.append(Op::Push(1000));
+        lr.publish();
+        assert_eq!(lr.into_inner(), (0..=1000).collect::<Vec<_>>());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_left_right_panic() {
+        use super::{Absorb, LeftRight};
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        struct Push(usize);
+        impl Absorb<Push> for Vec<usize> {
+            fn absorb_first(&mut self, op: &mut Push) {
+                self.push(op.0);
+            }
+            fn absorb_second(&mut self, op: Push) {
+                assert_ne!(op.0, 2, "absorb panic");
+                self.push(op.0);
+            }
+        }
+
+        let lr = LeftRight::new(vec![0]);
+        lr.append(Push(1));
+        lr.publish();
+        lr.extend([Push(2), Push(3)]);
+        let r = catch_unwind(AssertUnwindSafe(|| lr.publish()));
+        assert!(r.is_err());
+        // the published copy is intact, but the other one is half applied
+        assert_eq!(*lr.read(), [0, 1, 2, 3]);
+        assert_eq!(lr.pending(), 0);
+        for r in [
+            catch_unwind(AssertUnwindSafe(|| lr.append(Push(4)))),
+            catch_unwind(AssertUnwindSafe(|| lr.extend([Push(4)]))),
+            catch_unwind(AssertUnwindSafe(|| lr.publish())),
+        ] {
+            assert!(r.is_err());
+        }
+        assert_eq!(*lr.read(), [0, 1, 2, 3]);
+        assert_eq!(lr.into_inner(), [0, 1, 2, 3]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_update_panic() {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let t = RcuCell::new(10);
+        let v = t.read().unwrap();
+        let r = catch_unwind(AssertUnwindSafe(|| {
+            t.update(|_| -> Option<i32> { panic!("update panic") });
+        }));
+        assert!(r.is_err());
+        // the old value is restored and still valid
+        assert!(t.arc_eq(&v));
+        assert_eq!(Arc::strong_count(&v), 2);
+        let old = t.update(|v| v.map(|x| *x + 1));
+        assert_eq!(old.map(|v| *v), Some(10));
+        t.write(42);
+        assert_eq!(t.read().map(|v| *v), Some(42));
+
+        let t = super::RcuCellNonNull::new(10);
+        let r = catch_unwind(AssertUnwindSafe(|| {
+            t.update(|_| -> i32 { panic!("update panic") });
+        }));
+        assert!(r.is_err());
+        assert_eq!(*t.read(), 10);
+        t.update(|v| *v + 1);
+        t.write(12);
+        assert_eq!(*t.read(), 12);
+    }
+
+    #[test]
+    fn test_try_ops() {
+        use super::{RcuCellNonNull, RcuError, RcuWeak};
+
+        let t = RcuCell::new(10);
+        assert_eq!(t.try_read().unwrap().map(|v| *v), Some(10));
+        {
+            let _g = t.read_guard().unwrap();
+            // the value that is not written is given back
+            let err = t.try_write(11).unwrap_err();
+            assert_eq!((err.error, *err.new), (RcuError::WouldBlock, 11));
+            assert_eq!(t.try_take(), Err(RcuError::WouldBlock));
+            assert_eq!(t.try_update(|_| Some(12)), Err(RcuError::WouldBlock));
+        }
+        assert_eq!(t.read().map(|v| *v), Some(10));
+        let old = t.try_update(|v| v.map(|x| *x + 1)).unwrap();
+        assert_eq!(old.map(|v| *v), Some(10));
+        assert_eq!(t.try_write(12).unwrap().map(|v| *v), Some(11));
+        assert_eq!(t.try_take().unwrap().map(|v| *v), Some(12));
+        assert!(t.is_none());
+        t.try_set(Some(Arc::new(13))).unwrap();
+        let r = t.try_update(|v| {
+            // the update lock is held by us
+            assert_eq!(t.try_update(|_| Some(0)), Err(RcuError::Locked));
+            assert_eq!(t.try_write(0).unwrap_err().error, RcuError::Locked);
+            v
+        });
+        assert!(r.is_ok());
+        assert_eq!(t.read().map(|v| *v), Some(13));
+
+        let t = RcuCellNonNull::new(10);
+        assert_eq!(*t.try_read().unwrap(), 10);
+        {
+            let _g = t.read_guard();
+            let err = t.try_write(11).unwrap_err();
+            assert_eq!((err.error, *err.new), (RcuError::WouldBlock, 11));
+            assert_eq!(t.try_update(|v| *v + 1), Err(RcuError::WouldBlock));
+        }
+        assert_eq!(*t.try_update(|v| *v + 1).unwrap(), 10);
+        assert_eq!(*t.try_write(12).unwrap(), 11);
+
+        let v = Arc::new(10);
+        let t = RcuWeak::from(Arc::downgrade(&v));
+        assert_eq!(t.try_upgrade().unwrap(), Some(v.clone()));
+        assert!(t.try_read().unwrap().ptr_eq(&Arc::downgrade(&v)));
+        assert!(t.try_take().unwrap().ptr_eq(&Arc::downgrade(&v)));
+        assert!(t.try_write(Arc::downgrade(&v)).unwrap().upgrade().is_none());
+        assert_eq!(t.upgrade(), Some(v));
+    }
+
+    #[test]
+    fn test_many_readers() {
+        let t = RcuCell::new(10);
+        let guards: alloc::vec::Vec<_> = (0..5000).map(|_| t.read_guard().unwrap()).collect();
+        assert!(guards.iter().all(|g| **g == 10));
+        assert_eq!(t.try_read().unwrap().map(|v| *v), Some(10));
+        assert_eq!(
+            t.try_write(11).unwrap_err().error,
+            super::RcuError::WouldBlock
+        );
+        drop(guards);
+        assert_eq!(t.write(11).map(|v| *v), Some(10));
+        assert_eq!(t.read().map(|v| *v), Some(11));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_spilled_readers_block_writer() {
+        use core::sync::atomic::AtomicBool;
+
+        let t = RcuCell::new(10);
+        let written = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            let mut guards: std::vec::Vec<_> = (0..2000).map(|_| t.read_guard().unwrap()).collect();
+            // release the inline readers, keep the spilled ones
+            guards.drain(..1000);
+            s.spawn(|| {
+                t.write(11);
+                written.store(true, Ordering::Release);
+            });
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            assert!(!written.load(Ordering::Acquire));
+            assert!(guards.iter().all(|g| **g == 10));
+            drop(guards);
+        });
+        assert!(written.load(Ordering::Acquire));
+        assert_eq!(t.read().map(|v| *v), Some(11));
+    }
+
+    #[test]
+    fn test_version() {
+        let t = RcuCell::new(10);
+        assert_eq!(t.version(), 0);
+        let (v, ver) = t.read_versioned();
+        assert_eq!((v.map(|v| *v), ver), (Some(10), 0));
+
+        let a = t.write(11).unwrap();
+        assert_eq!(t.version(), 1);
+        t.take();
+        assert_eq!(t.version(), 2);
+        // the same allocation written back is still a new version
+        t.write(a.clone());
+        assert!(t.arc_eq(&a));
+        assert_eq!(t.read_versioned().1, 3);
+        t.update(|v| v.map(|x| *x + 1));
+        assert_eq!(t.version(), 4);
+        let cur = t.read();
+        assert!(t.compare_and_set(None, None).is_err());
+        assert_eq!(t.version(), 4);
+        t.compare_and_set(cur.as_ref(), None).unwrap();
+        assert_eq!(t.version(), 5);
+        t.fetch_update(|_| Some(Some(1))).unwrap();
+        assert_eq!(t.version(), 6);
+        assert_eq!(t.try_write(2).unwrap().map(|v| *v), Some(1));
+        assert_eq!(
+            t.try_update(|_| Some(3)).map(|v| v.map(|v| *v)),
+            Ok(Some(2))
+        );
+        let (v, ver) = t.read_versioned();
+        assert_eq!((v.map(|v| *v), ver), (Some(3), 8));
+
+        let w = super::RcuWeak::new();
+        w.write_arc(&a);
+        assert_eq!(w.version(), 1);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_read_versioned_consistent() {
+        use std::sync::atomic::AtomicBool;
+
+        // every update adds one to the value, so the value is always the
+        // same as the version it's published with
+        let t = super::RcuCellNonNull::new(0u64);
+        let done = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..1000 {
+                        t.update(|v| *v + 1);
+                    }
+                });
+            }
+            for _ in 0..4 {
+                s.spawn(|| {
+                    while !done.load(Ordering::Relaxed) {
+                        let (v, ver) = t.read_versioned();
+                        assert_eq!(*v, ver);
+                    }
+                });
+            }
+            while t.version() < 4000 {
+                std::thread::yield_now();
+            }
+            done.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(*t.read(), 4000);
+    }
+
+    #[test]
+    fn test_cache() {
+        let t = RcuCell::new(10);
+        let mut cache = t.cache();
+        assert_eq!(cache.load().as_deref(), Some(&10));
+        assert_eq!(cache.version(), 0);
+        // the cache holds its own value, so it doesn't block the writers
+        let a = t.try_write(11).unwrap().unwrap();
+        assert_eq!(cache.cached().as_deref(), Some(&10));
+        assert_eq!(cache.load().as_deref(), Some(&11));
+        t.take();
+        assert!(cache.load().is_none());
+        // the same allocation written back is still detected
+        t.write(a.clone());
+        assert!(Arc::ptr_eq(cache.load().as_ref().unwrap(), &a));
+        assert_eq!(cache.version(), 3);
+        let mut c2 = cache.clone();
+        t.write(12);
+        assert_eq!(cache.load().as_deref(), Some(&12));
+        assert_eq!(c2.load().as_deref(), Some(&12));
+
+        use alloc::{format, string::String};
+        let t = super::RcuCellNonNull::new(String::from("a"));
+        let mut cache = super::RcuCache::from(&t);
+        assert_eq!(cache.load().as_str(), "a");
+        t.update(|v| format!("{v}b"));
+        assert_eq!(cache.load().as_str(), "ab");
+        assert!(core::ptr::eq(cache.cell(), &t));
+    }
+
+    #[cfg(feature = "std")]
+    fn block_on<F: core::future::Future>(fut: F) -> F::Output {
+        use std::task::{Context, Poll, Wake, Waker};
+
+        struct ThreadWaker(std::thread::Thread);
+        impl Wake for ThreadWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+
+        let waker = Waker::from(Arc::new(ThreadWaker(std::thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = core::pin::pin!(fut);
+        loop {
+            match fut.as_mut().poll(&mut cx) {
+                Poll::Ready(v) => return v,
+                Poll::Pending => std::thread::park(),
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_subscriber_poll() {
+        use std::task::{Context, Poll, Wake, Waker};
+
+        struct CountWaker(AtomicUsize);
+        impl Wake for CountWaker {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+
+        let count = Arc::new(CountWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(count.clone());
+        let mut cx = Context::from_waker(&waker);
+        let woken = || count.0.load(Ordering::Relaxed);
+
+        let t = RcuCell::new(10);
+        let mut sub = t.subscribe();
+        assert_eq!(sub.poll_changed(&mut cx), Poll::Pending);
+        // polling again doesn't register the same waker twice
+        assert_eq!(sub.poll_changed(&mut cx), Poll::Pending);
+        t.write(11);
+        assert_eq!(woken(), 1);
+        assert!(sub.has_changed());
+        assert_eq!(sub.poll_changed(&mut cx), Poll::Ready(()));
+        assert!(!sub.has_changed());
+
+        assert!(sub.poll_next(&mut cx).is_pending());
+        // a failed compare and set is not a change
+        assert!(t.compare_and_set(None, None).is_err());
+        assert_eq!(woken(), 1);
+        t.take();
+        assert_eq!(woken(), 2);
+        assert_eq!(sub.poll_next(&mut cx), Poll::Ready(Some(None)));
+        t.update(|_| Some(12));
+        assert_eq!(woken(), 2);
+        assert_eq!(sub.read().map(|v| *v), Some(12));
+        assert!(sub.poll_next(&mut cx).is_pending());
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_subscriber_async() {
+        let t = Arc::new(super::RcuCellNonNull::new(0));
+        let mut sub = super::Subscriber::new(t.clone());
+        let h = std::thread::spawn(move || {
+            block_on(async {
+                let mut last = 0;
+                while last < 100 {
+                    let v = sub.next().await.unwrap();
+                    // the values may be skipped but never go back
+                    assert!(*v > last);
+                    last = *v;
+                }
+                if last < 200 {
+                    sub.changed().await;
+                }
+                *sub.read()
+            })
+        });
+        for i in 1..=100 {
+            t.write(i);
+            std::thread::sleep(std::time::Duration::from_micros(100));
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        t.write(200);
+        assert_eq!(h.join().unwrap(), 200);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_wait() {
+        use std::time::{Duration, Instant};
+
+        #[derive(Debug, Default)]
+        struct Config {
+            ready: bool,
+            id: usize,
+        }
+
+        let t = RcuCell::new(Config::default());
+        let now = Instant::now();
+        let err = t.wait_for_change(Duration::from_millis(20)).unwrap_err();
+        assert_eq!(err, super::RcuError::Timeout);
+        assert!(now.elapsed() >= Duration::from_millis(20));
+        let err = t.wait_until(|v| v.is_none(), Duration::from_millis(1));
+        assert_eq!(err.unwrap_err(), super::RcuError::Timeout);
+
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                for id in 1..=5 {
+                    std::thread::sleep(Duration::from_millis(1));
+                    t.write(Config { ready: id == 5, id });
+                }
+            });
+            let v = t.wait_until(|v| v.as_ref().is_some_and(|v| v.ready), Duration::MAX);
+            assert_eq!(v.unwrap().unwrap().id, 5);
+        });
+        // the value is checked before waiting
+        let v = t.wait_until(|v| v.is_some(), Duration::ZERO).unwrap();
+        assert_eq!(v.unwrap().id, 5);
+
+        std::thread::scope(|s| {
+            let h = s.spawn(|| t.wait_for_change(Duration::MAX));
+            std::thread::sleep(Duration::from_millis(10));
+            t.take();
+            assert!(h.join().unwrap().unwrap().is_none());
+        });
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_wait_timeout() {
+        use std::time::Duration;
+
+        let t = RcuCell::new(1);
+        // the waits that time out leave the same waker of the thread
+        for _ in 0..1000 {
+            assert!(t.wait_for_change(Duration::ZERO).is_err());
+            assert!(t.wait_until(|v| v.is_none(), Duration::ZERO).is_err());
+        }
+        assert_eq!(t.link.waiters(), 1);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                for _ in 0..1000 {
+                    assert!(t.wait_for_change(Duration::from_nanos(1)).is_err());
+                }
+            });
+        });
+        assert_eq!(t.link.waiters(), 2);
+        // the wakers are consumed by the next write
+        t.write(2);
+        assert_eq!(t.link.waiters(), 0);
+    }
+
+    #[test]
+    fn test_indirect_link() {
+        use super::link::LinkWrapper;
+        use super::SpinThenPark as W;
+        use core::ptr::NonNull;
+
+        let data = [0u8; 16];
+        // unaligned pointers can't be packed into the link directly
+        let p1 = Some(NonNull::from(&data[1]));
+        let p2 = Some(NonNull::from(&data[3]));
+        let p3 = Some(NonNull::from(&data[8]));
+
+        let mut link = LinkWrapper::new(p1);
+        assert_eq!(link.get_ref::<W>(), p1);
+        let (ptr, kind) = link.inc_ref::<W>();
+        assert_eq!(ptr, p1);
+        link.dec_ref(kind);
+        assert_eq!(link.update::<W>(p2), p1);
+        assert_eq!(link.get_ref::<W>(), p2);
+        assert_eq!(link.try_update::<W>(p3), Ok(p2));
+        assert_eq!(link.update::<W>(p1), p3);
+
+        let guard = link.lock_update::<W>();
+        assert_eq!(guard.ptr(), p1);
+        assert_eq!(guard.unlock(p2), p1);
+        let guard = link.lock_update::<W>();
+        drop(guard);
+        assert_eq!(link.get_ref::<W>(), p2);
+
+        unsafe {
+            assert_eq!(link.compare_exchange::<W>(p1, p3), Err(p2));
+            assert_eq!(link.compare_exchange::<W>(p2, p3), Ok(p2));
+            assert_eq!(link.compare_exchange::<W>(p3, p1), Ok(p3));
+            assert_eq!(link.compare_exchange::<W>(p3, p1), Err(p1));
+        }
+        assert!(!link.is_none());
+        assert_eq!(link.take_ptr(), p1);
+        assert!(link.is_none());
+
+        // the tagged addresses use the high bits that could never be packed,
+        // the link is never dereferenced without the credits
+        #[cfg(target_pointer_width = "64")]
+        {
+            let t1 = NonNull::new(0xff00_0000_0000_1000 as *mut u8);
+            let t2 = NonNull::new(0x0b00_7fff_0000_2000 as *mut u8);
+            let mut link = LinkWrapper::new(t1);
+            assert_eq!(link.get_ref::<W>(), t1);
+            assert_eq!(link.update::<W>(t2), t1);
+            assert_eq!(link.pin::<W>().ptr(), t2);
+            // locked in the second generation
+            let guard = link.lock_update::<W>();
+            assert_eq!(guard.ptr(), t2);
+            assert_eq!(guard.unlock(t1), t2);
+            assert_eq!(link.try_update::<W>(t2), Ok(t1));
+            assert_eq!(link.update::<W>(p1), t2);
+            assert_eq!(link.update::<W>(t1), p1);
+            assert_eq!(link.take_ptr(), t1);
+
+            // the address that marks the indirect words is stored indirectly
+            let t3 = NonNull::new(8 as *mut u8);
+            let mut link = LinkWrapper::new(t3);
+            assert_eq!(link.get_ref::<W>(), t3);
+            assert_eq!(link.update::<W>(t1), t3);
+            assert_eq!(link.update::<W>(t3), t1);
+            assert_eq!(link.pin::<W>().ptr(), t3);
+            assert_eq!(link.take_ptr(), t3);
+        }
+    }
+
+    #[test]
+    fn test_read_guard() {
+        let t = RcuCell::new(10);
+        {
+            let g1 = t.read_guard().unwrap();
+            let g2 = t.read_guard().unwrap();
+            assert_eq!(*g1, 10);
+            assert_eq!(*g2, 10);
+        }
+        assert_eq!(t.with(|v| v.copied()), Some(10));
+        t.write(11);
+        assert_eq!(*t.read_guard().unwrap(), 11);
+        t.take();
+        assert!(t.read_guard().is_none());
+        assert_eq!(t.with(|v| v.copied()), None);
+
+        let t = super::RcuCellNonNull::new(String::from("hello"));
+        assert_eq!(t.with(|v| v.len()), 5);
+        let g = t.read_guard();
+        assert_eq!(&*g, "hello");
+        drop(g);
+        t.write(String::from("world"));
+        assert_eq!(&*t.read_guard(), "world");
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_read_guard_block_writer() {
+        use core::sync::atomic::AtomicBool;
+
+        let t = RcuCell::new(10);
+        let written = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            let g = t.read_guard().unwrap();
+            s.spawn(|| {
+                t.write(11);
+                written.store(true, Ordering::Release);
+            });
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            // the writer must wait for the guard
+            assert!(!written.load(Ordering::Acquire));
+            assert_eq!(*g, 10);
+            drop(g);
+        });
+        assert!(written.load(Ordering::Acquire));
+        assert_eq!(t.read().map(|v| *v), Some(11));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_publish_before_readers_released() {
+        let t = RcuCell::new(10);
+        std::thread::scope(|s| {
+            let g = t.read_guard().unwrap();
+            let h = s.spawn(|| t.write(11));
+            // the new value is published at once, only handing out the old
+            // value waits for the guard
+            while t.read().map(|v| *v) != Some(11) {
+                std::thread::yield_now();
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            assert!(!h.is_finished());
+            assert_eq!(*g, 10);
+            drop(g);
+            assert_eq!(h.join().unwrap().map(|v| *v), Some(10));
+        });
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_writer_not_starved() {
+        use core::sync::atomic::AtomicBool;
+        use std::sync::Barrier;
+
+        let t = RcuCell::new(0usize);
+        let stop = AtomicBool::new(false);
+        let start = Barrier::new(6);
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    start.wait();
+                    while !stop.load(Ordering::Relaxed) {
+                        let v = t.read().unwrap();
+                        assert!(*t.read_guard().unwrap() >= *v);
+                        std::thread::yield_now();
+                    }
+                });
+            }
+            // keep some readers spilled all the time
+            s.spawn(|| {
+                start.wait();
+                while !stop.load(Ordering::Relaxed) {
+                    let guards: std::vec::Vec<_> = (0..100).map(|_| t.read_guard()).collect();
+                    assert!(guards.iter().all(|g| g.is_some()));
+                    drop(guards);
+                    std::thread::yield_now();
+                }
+            });
+            start.wait();
+            for i in 1..=200 {
+                t.write(i);
+                t.update(|v| v.map(|v| *v));
+            }
+            stop.store(true, Ordering::Relaxed);
+        });
+        assert_eq!(t.read().map(|v| *v), Some(200));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_write_timeout() {
+        use core::sync::atomic::AtomicBool;
+        use std::time::Duration;
+
+        let t = super::RcuCellNonNull::new(10);
+        let g = t.read_guard();
+        let err = t.write_timeout(11, Duration::from_millis(20)).unwrap_err();
+        assert_eq!((err.error, *err.new), (super::RcuError::Timeout, 11));
+        let err = t.update_timeout(|v| *v + 1, Duration::from_millis(20));
+        assert_eq!(err.unwrap_err(), super::RcuError::Timeout);
+        assert_eq!(*g, 10);
+        drop(g);
+        assert_eq!(*t.write_timeout(11, Duration::MAX).unwrap(), 10);
+
+        let called = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                t.update(|v| {
+                    std::thread::sleep(Duration::from_millis(50));
+                    *v + 1
+                })
+            });
+            std::thread::sleep(Duration::from_millis(10));
+            // the lock is not acquired in time, the closure is not called
+            let err = t.update_timeout(
+                |v| {
+                    called.store(true, Ordering::Relaxed);
+                    *v
+                },
+                Duration::from_millis(10),
+            );
+            assert_eq!(err.unwrap_err(), super::RcuError::Timeout);
+            // parked until the lock is released
+            let old = t.update_timeout(|v| *v + 1, Duration::MAX).unwrap();
+            assert_eq!(*old, 12);
+        });
+        assert!(!called.load(Ordering::Relaxed));
+        assert_eq!(*t.read(), 13);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_parked_writers() {
+        let t = RcuCell::new(0);
+        std::thread::scope(|s| {
+            let g = t.read_guard().unwrap();
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        t.update(|v| v.map(|v| *v + 1));
+                    }
+                });
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            assert_eq!(*g, 0);
+            drop(g);
+            for _ in 0..100 {
+                let _g = t.read_guard();
+            }
+        });
+        assert_eq!(t.read().map(|v| *v), Some(400));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_wait_strategy() {
+        use super::{RcuCellNonNull, RcuError, Spin, SpinThenYield, WaitStrategy};
+
+        static SNOOZED: AtomicUsize = AtomicUsize::new(0);
+
+        struct Counted;
+
+        impl WaitStrategy for Counted {
+            fn snooze(step: u32) -> bool {
+                SNOOZED.fetch_add(1, Ordering::Relaxed);
+                step < 3
+            }
+        }
+
+        let t: RcuCell<i32, Spin> = RcuCell::new(1).with_strategy();
+        let g = t.read_guard();
+        assert_eq!(t.try_write(2).unwrap_err().error, RcuError::WouldBlock);
+        drop(g);
+        assert_eq!(t.write(2).map(|v| *v), Some(1));
+
+        let t = RcuCellNonNull::new(0).with_strategy::<SpinThenYield>();
+        std::thread::scope(|s| {
+            for _ in 0..4 {
+                s.spawn(|| {
+                    for _ in 0..100 {
+                        t.update(|v| *v + 1);
+                    }
+                });
+            }
+        });
+        assert_eq!(*t.read(), 400);
+
+        let t = RcuCellNonNull::new(0).with_strategy::<Counted>();
+        let g = t.read_guard();
+        assert_eq!(t.try_write(1).unwrap_err().error, RcuError::WouldBlock);
+        // the try operation gives up once the strategy says park
+        assert_eq!(SNOOZED.load(Ordering::Relaxed), 4);
+        std::thread::scope(|s| {
+            s.spawn(|| t.write(2));
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            drop(g);
+        });
+        assert_eq!(*t.read(), 2);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_epoch_rcu_cell() {
+        use super::EpochRcuCell;
+
+        static LIVE: AtomicUsize = AtomicUsize::new(0);
+        let foo = |data| Live::new(data, &LIVE);
+
+        let t = EpochRcuCell::new(foo(0));
+        let g = t.read_guard().unwrap();
+        for i in 1..=10 {
+            // the writers never wait for the guard
+            assert_eq!(t.write(foo(i)).map(|v| v.0), Some(i - 1));
+        }
+        assert_eq!(g.0, 0);
+        assert_eq!(t.with(|v| v.map(|v| v.0)), Some(10));
+        // the retired values are kept alive by the guard
+        assert_eq!(LIVE.load(Ordering::Relaxed), 11);
+        drop(g);
+        // other tests may pin the epoch for a while, the writers collect the
+        // retired values once they are unpinned
+        let mut i = 11;
+        eventually("the retired values are collected", || {
+            t.write(foo(i));
+            i += 1;
+            LIVE.load(Ordering::Relaxed) <= 3
+        });
+
+        check_reclaim_cell(t, foo);
+        // the values that are still retired are dropped with the cell
+        assert_eq!(LIVE.load(Ordering::Relaxed), 0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_hazard_rcu_cell() {
+        use super::HazardRcuCell;
+
+        static LIVE: AtomicUsize = AtomicUsize::new(0);
+        let foo = |data| Live::new(data, &LIVE);
+
+        let t = HazardRcuCell::new(foo(0));
+        let g = t.read_guard().unwrap();
+        for i in 1..=1000 {
+            // the writers never wait for the guard
+            assert_eq!(t.write(foo(i)).map(|v| v.0), Some(i - 1));
+        }
+        // only the protected value is kept alive by the guard
+        assert_eq!(g.0, 0);
+        assert!(LIVE.load(Ordering::Relaxed) < 500);
+        assert_eq!(t.with(|v| v.map(|v| v.0)), Some(1000));
+        drop(g);
+
+        check_reclaim_cell(t, foo);
+        // the retired values are freed by the later scans
+        let t = HazardRcuCell::new(0);
+        eventually("the retired values are scanned", || {
+            t.write(0);
+            LIVE.load(Ordering::Relaxed) == 0
+        });
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_striped_rcu_cell() {
+        use super::StripedRcuCell;
+        use core::sync::atomic::AtomicBool;
+
+        static LIVE: AtomicUsize = AtomicUsize::new(0);
+        let foo = |data| Live::new(data, &LIVE);
+
+        let t = StripedRcuCell::new(foo(0));
+        assert!(t.stripes().is_power_of_two());
+        let done = AtomicBool::new(false);
+        std::thread::scope(|s| {
+            let g = t.read_guard().unwrap();
+            let writer = s.spawn(|| {
+                let old = t.write(foo(1));
+                done.store(true, Ordering::Release);
+                old
+            });
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            // the writer waits the guard before returning the old value
+            assert!(!done.load(Ordering::Acquire));
+            assert_eq!(g.0, 0);
+            // the new readers don't block the writer
+            assert_eq!(t.read().map(|v| v.0), Some(1));
+            drop(g);
+            assert_eq!(writer.join().unwrap().map(|v| v.0), Some(0));
+        });
+        assert_eq!(LIVE.load(Ordering::Relaxed), 1);
+
+        check_reclaim_cell(t, foo);
+        // the old values are never kept by the cell
+        assert_eq!(LIVE.load(Ordering::Relaxed), 0);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_rcu_domain() {
+        use super::RcuDomain;
+        use core::sync::atomic::AtomicBool;
+        use std::sync::Mutex;
+
+        static LIVE: AtomicUsize = AtomicUsize::new(0);
+        let foo = |data| Live::new(data, &LIVE);
+
+        for domain in [RcuDomain::new(), RcuDomain::with_membarrier()] {
+            let cell = domain.cell(foo(0));
+            let synced = AtomicBool::new(false);
+            let gp = domain.gp();
+            std::thread::scope(|s| {
+                let thread = domain.register();
+                let guard = thread.read_lock();
+                let nested = thread.read_lock();
+                let v = cell.read(&guard).unwrap();
+                s.spawn(|| {
+                    let old = cell.replace(Some(foo(1)));
+                    assert_eq!(old.map(|v| v.0), Some(0));
+                    synced.store(true, Ordering::Release);
+                });
+                // wait the grace period to start, so the reader below is new
+                while domain.gp() == gp {
+                    std::thread::yield_now();
+                }
+                std::thread::sleep(std::time::Duration::from_millis(20));
+                // the old value is kept alive by the reader
+                assert!(!synced.load(Ordering::Acquire));
+                assert_eq!(v.0, 0);
+                drop(nested);
+                assert_eq!(cell.read(&guard).map(|v| v.0), Some(1));
+                drop(guard);
+                // the new readers are not waited
+                let _guard = thread.read_lock();
+                while !synced.load(Ordering::Acquire) {
+                    std::thread::yield_now();
+                }
+            });
+            domain.barrier();
+            assert_eq!(LIVE.load(Ordering::Relaxed), 1);
+            assert_eq!(cell.replace(None).map(|v| v.0), Some(1));
+
+            let order = std::sync::Arc::new(Mutex::new(Vec::new()));
+            for i in 0..10 {
+                let order = order.clone();
+                domain.call_rcu(Box::new(move || order.lock().unwrap().push(i)));
+            }
+            domain.barrier();
+            assert_eq!(*order.lock().unwrap(), (0..10).collect::<Vec<_>>());
+            // the pending callbacks are called when the domain is dropped
+            domain.call_rcu(Box::new(move || order.lock().unwrap().clear()));
+            cell.write(foo(2));
+            drop(cell);
+        }
+        assert_eq!(LIVE.load(Ordering::Relaxed), 0);
+    }
+
+    #[cfg(all(feature = "membarrier", target_os = "linux"))]
+    #[test]
+    fn test_membarrier_fallback() {
+        use super::membarrier;
+        use super::RcuDomain;
+
+        // the registration fails, e.g. the syscall is filtered by seccomp
+        let registered = membarrier::register_with(|_| -1);
+        assert!(!registered);
+        let domain = RcuDomain::with(registered);
+        assert!(!domain.uses_membarrier());
+        // the readers are ordered by their own fences instead
+        let cell = domain.cell(0);
+        std::thread::scope(|s| {
+            let thread = domain.register();
+            let guard = thread.read_lock();
+            let v = cell.read(&guard).unwrap();
+            let writer = s.spawn(|| {
+                cell.write(1);
+                domain.synchronize();
+            });
+            std::thread::sleep(std::time::Duration::from_millis(20));
+            assert!(!writer.is_finished());
+            assert_eq!(*v, 0);
+            drop(guard);
+            writer.join().unwrap();
+        });
+        let thread = domain.register();
+        assert_eq!(cell.read(&thread.read_lock()).copied(), Some(1));
+    }
+
+    #[test]
+    fn test_rcu_cell_of() {
+        use super::RcuCellOf;
+        use alloc::boxed::Box;
+
+        let b = RcuCellOf::from_pointer(Box::new(10));
+        assert_eq!(*b.set(Box::new(11)), 10);
+        assert_eq!(*b.try_set(Box::new(12)).unwrap(), 11);
+        assert_eq!(*b.into_pointer(), 12);
+
+        let b = RcuCellOf::<Option<Box<i32>>>::from_pointer(None);
+        assert!(b.set(Some(Box::new(1))).is_none());
+        assert_eq!(b.set(None).map(|v| *v), Some(1));
+
+        static BYTES: [u8; 4] = [1, 2, 3, 4];
+        // the odd address is stored in the indirect slot of the link
+        let s = RcuCellOf::from_pointer(&BYTES[1]);
+        assert_eq!(*s.read(), 2);
+        assert!(s.pointer_eq(&&BYTES[1]));
+        assert_eq!(*s.set(&BYTES[2]), 2);
+        assert_eq!(*s.try_read().unwrap(), 3);
+        let s2 = RcuCellOf::from_pointer(&BYTES[2]);
+        assert!(RcuCellOf::ptr_eq(&s, &s2));
+
+        let o = RcuCellOf::<Option<&'static u8>>::from_pointer(None);
+        assert!(o.read().is_none());
+        o.set(Some(&BYTES[3]));
+        assert_eq!(o.read(), Some(&4));
+
+        let a = Arc::new(5);
+        let c = RcuCell::from(a.clone());
+        assert!(c.pointer_eq(&Some(a.clone())));
+        let w = super::RcuWeak::from(Arc::downgrade(&a));
+        assert_eq!(w.read().upgrade(), Some(a));
+    }
+
+    #[test]
+    fn test_unsized() {
+        use super::{RcuCellNonNull, RcuCellOf};
+        use alloc::boxed::Box;
+        use alloc::string::{String, ToString};
+
+        let s = RcuCell::<str>::from(Arc::from("hello"));
+        assert_eq!(s.read().as_deref(), Some("hello"));
+        let old = s.write("world");
+        assert_eq!(old.as_deref(), Some("hello"));
+        assert_eq!(s.with(|v| v.map(str::len)), Some(5));
+        s.update(|v| v.map(|v| v.to_uppercase()));
+        assert_eq!(&*s.read_guard().unwrap(), "WORLD");
+        let cur = s.read();
+        assert!(s.arc_eq(cur.as_ref().unwrap()));
+        let old = s.compare_and_set(cur.as_ref(), None).unwrap();
+        assert_eq!(old, cur);
+        assert!(s.is_none());
+        // null is packed even for the fat pointers
+        assert_eq!(s.read(), None);
+        assert!(RcuCell::<str>::none().read().is_none());
+        s.write("again");
+        assert_eq!(s.take().as_deref(), Some("again"));
+        assert!(s.take().is_none());
+
+        let b = RcuCellNonNull::<[u8]>::from(Arc::from(&[1u8, 2, 3][..]));
+        assert_eq!(&*b.read(), &[1, 2, 3]);
+        let old = b.fetch_update(|v| Some(v.iter().map(|x| x * 2).collect::<Arc<[u8]>>()));
+        assert_eq!(&*old.unwrap(), &[1, 2, 3]);
+        assert_eq!(&*b.read_guard(), &[2, 4, 6]);
+
+        trait Handler: Send + Sync {
+            fn handle(&self) -> String;
+        }
+        struct Hello;
+        impl Handler for Hello {
+            fn handle(&self) -> String {
+                "hello".to_string()
+            }
+        }
+        struct Num(usize);
+        impl Handler for Num {
+            fn handle(&self) -> String {
+                self.0.to_string()
+            }
+        }
+
+        let h = RcuCellNonNull::<dyn Handler>::from(Arc::new(Hello) as Arc<dyn Handler>);
+        let r: Arc<dyn Handler> = h.read();
+        assert_eq!(r.handle(), "hello");
+        h.write(Arc::new(Num(42)) as Arc<dyn Handler>);
+        assert_eq!(h.read().handle(), "42");
+        assert_eq!(r.handle(), "hello");
+        assert!(h.compare_and_set(&r, r.clone()).is_err());
+        let cur = h.read();
+        assert!(h.compare_and_set(&cur, r.clone()).is_ok());
+        assert!(h.arc_eq(&r));
+
+        // the fat pointer of `&'static str` is stored in an indirect slot
+        let r = RcuCellOf::<&'static str>::from_pointer("abc");
+        assert_eq!(r.set("defg"), "abc");
+        assert_eq!(r.read(), "defg");
+        let b = RcuCellOf::<Option<Box<[u32]>>>::from_pointer(None);
+        b.set(Some(Box::new([1, 2])));
+        assert_eq!(b.set(None).as_deref(), Some(&[1, 2][..]));
+    }
+
     #[cfg(feature = "serde")]
     #[test]
     fn test_serde() {
@@ -236,12 +1720,11 @@ mod test {
 }
 
 #[cfg(feature = "serde")]
-
 mod ser {
     use super::*;
     use serde::{Deserialize, Serialize};
 
-    impl<T: Serialize> Serialize for RcuCell<T>
+    impl<T: Serialize, W: WaitStrategy> Serialize for RcuCell<T, W>
     where
         Arc<T>: Serialize,
     {
@@ -253,13 +1736,13 @@ mod ser {
         }
     }
 
-    impl<'de, T: Deserialize<'de>> Deserialize<'de> for RcuCell<T> {
+    impl<'de, T: Deserialize<'de>, W: WaitStrategy> Deserialize<'de> for RcuCell<T, W> {
         fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
         where
             D: serde::Deserializer<'de>,
         {
             let value = T::deserialize(deserializer)?;
-            Ok(RcuCell::new(value))
+            Ok(RcuCell::new(value).with_strategy())
         }
     }
 }
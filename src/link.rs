@@ -1,23 +1,206 @@
+use core::cell::UnsafeCell;
 use core::fmt;
 use core::marker::PhantomData;
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::panic::RefUnwindSafe;
+use core::ptr::NonNull;
+use core::sync::atomic::{fence, AtomicU64, AtomicUsize, Ordering};
+use core::task::Waker;
 
-const LEADING_BITS: usize = 8;
-const ALIGN_BITS: usize = 3;
+#[cfg(feature = "std")]
+use crate::notify::thread_waker;
+use crate::notify::Notifier;
+#[cfg(feature = "std")]
+use crate::park::Deadline;
+use crate::park::{Wait, Waiter};
+use crate::strategy::{SpinThenPark, WaitStrategy};
+use crate::RcuError;
 
-const LOWER_MASK: usize = (1 << ALIGN_BITS) - 1;
-const HIGHER_MASK: usize = !((1 << (usize::MAX.leading_ones() as usize - LEADING_BITS)) - 1);
-const REFCOUNT_MASK: usize = (1 << (LEADING_BITS + ALIGN_BITS)) - 1;
-const UPDTATE_MASK: usize = 1 << (LEADING_BITS + ALIGN_BITS - 1);
-const UPDATE_REF_MASK: usize = REFCOUNT_MASK & !UPDTATE_MASK;
+#[cfg(target_pointer_width = "64")]
+mod layout {
+    //! the pointer is shifted into the high bits of the word, the low bits
+    //! that are freed by the alignment and the unused leading bits hold the
+    //! generation, the credits and the reader count. Only the addresses below
+    //! 48 bits are packed, which covers the user space of the common 64-bit
+    //! platforms, the others are stored in the indirect slots of the link,
+    //! they are never credited and the readers clone them under a reader
+    pub(super) type Word = usize;
+    pub(super) type AtomicWord = core::sync::atomic::AtomicUsize;
+
+    const LEADING_BITS: usize = 16;
+    const ALIGN_BITS: usize = 3;
+
+    const LOWER_MASK: usize = (1 << ALIGN_BITS) - 1;
+    const HIGHER_MASK: usize = !((1 << (usize::MAX.leading_ones() as usize - LEADING_BITS)) - 1);
+    // the smallest aligned address is reserved to mark the indirect words
+    const INDIRECT_ADDR: usize = 1 << ALIGN_BITS;
+    pub(super) const REFCOUNT_MASK: Word = (1 << (LEADING_BITS + ALIGN_BITS)) - 1;
+    // the ptr is stored in the indirect slot of its generation
+    pub(super) const INDIRECT: Word = pack(INDIRECT_ADDR);
+    // the bits that identify the stored pointer
+    pub(super) const LINK_MASK: Word = !REFCOUNT_MASK;
+    // the generation of the ptr, flipped by each publish
+    pub(super) const GEN_MASK: Word = 1 << (LEADING_BITS + ALIGN_BITS - 1);
+    // the credits that are taken by the readers, the 8 bits below the
+    // generation, the 10 bits below them are the reader count
+    pub(super) const CREDIT_MASK: Word = 0xff << (LEADING_BITS + ALIGN_BITS - 9);
+    pub(super) const CREDIT_ONE: Word = 1 << (LEADING_BITS + ALIGN_BITS - 9);
+    pub(super) const REF_ONE: Word = 1;
+
+    /// check if the address could be packed into the link directly
+    #[inline]
+    pub(super) const fn fits(addr: usize) -> bool {
+        addr & (LOWER_MASK | HIGHER_MASK) == 0 && addr != INDIRECT_ADDR
+    }
+
+    #[inline]
+    pub(super) const fn pack(addr: usize) -> Word {
+        addr << LEADING_BITS
+    }
+
+    #[inline]
+    pub(super) const fn unpack(word: Word) -> usize {
+        (word & !REFCOUNT_MASK) >> LEADING_BITS
+    }
+}
+
+#[cfg(target_pointer_width = "32")]
+mod layout {
+    //! the pointer is stored in the low half of a 64-bit word, the high half
+    //! holds the indirect flag, the generation, the credits and the reader
+    //! count
+    pub(super) type Word = u64;
+    pub(super) type AtomicWord = core::sync::atomic::AtomicU64;
+
+    pub(super) const REFCOUNT_MASK: Word = !(u32::MAX as Word);
+    // the ptr is stored in the indirect slot of its generation
+    pub(super) const INDIRECT: Word = 1 << 63;
+    // the bits that identify the stored pointer
+    pub(super) const LINK_MASK: Word = !REFCOUNT_MASK | INDIRECT;
+    // the generation of the ptr, flipped by each publish
+    pub(super) const GEN_MASK: Word = 1 << 62;
+    // the credits that are taken by the readers, the 8 bits below the
+    // generation
+    pub(super) const CREDIT_MASK: Word = 0xff << 54;
+    pub(super) const CREDIT_ONE: Word = 1 << 54;
+    pub(super) const REF_ONE: Word = 1 << 32;
+
+    /// check if the address could be packed into the link directly
+    #[inline]
+    pub(super) const fn fits(_addr: usize) -> bool {
+        true
+    }
+
+    #[inline]
+    pub(super) const fn pack(addr: usize) -> Word {
+        addr as Word
+    }
+
+    #[inline]
+    pub(super) const fn unpack(word: Word) -> usize {
+        word as u32 as usize
+    }
+}
+
+use layout::*;
+
+const UPDATE_REF_MASK: Word = REFCOUNT_MASK & !INDIRECT & !GEN_MASK & !CREDIT_MASK;
+// readers would spill to the side counter once the inline counter reaches
+// this value, the counter is checked before it's increased so it never goes
+// beyond, the mutating writer adds the same value as a sentinel
+const SPILL_REFS: Word = ((UPDATE_REF_MASK >> 1) & UPDATE_REF_MASK) + REF_ONE;
+// the update lock of the link, kept in the state word of the writers so
+// that the link word leaves the bits to the reader count
+const LOCKED: usize = 1;
+// some writers are parked until the link is unlocked or the readers are
+// released
+const WAITING: usize = 2;
+// the ptr is mutated in place, the new readers wait until it's done
+const MUTATING: usize = 4;
+// some writers are parked until the spilled readers are released
+const SPILL_WAITING: usize = !(usize::MAX >> 1);
+// the retired generation is not drained yet, the readers that still count on
+// it are added to or subtracted from this bias
+const DRAINING: usize = 1 << (usize::BITS - 2);
+// some writers are parked until the retired generation is drained
+const DRAIN_WAITING: usize = 1 << (usize::BITS - 1);
+// the most references that are credited to a packed ptr in advance, the
+// rest of the credit bits is the headroom for the readers that race past the
+// batch before they back off
+const CREDIT_BATCH: usize = 64;
+// a new ptr is published with all its credits taken, so that no reference is
+// credited to the ptr that is never read, the first reader would refill them
+#[allow(clippy::unnecessary_cast)] // the word is u64 on 32-bit platforms
+const CREDITS_TAKEN: Word = CREDIT_BATCH as Word * CREDIT_ONE;
+
+/// check if the ptr of the word is stored in the indirect slot
+#[inline]
+fn is_indirect(word: Word) -> bool {
+    word & LINK_MASK == INDIRECT
+}
+
+/// the number of the inline readers in the word
+#[inline]
+#[allow(clippy::unnecessary_cast)] // the word is u64 on 32-bit platforms
+fn inline_refs(word: Word) -> usize {
+    ((word & UPDATE_REF_MASK) / REF_ONE) as usize
+}
+
+/// the number of the credits that are taken from the word
+#[inline]
+#[allow(clippy::unnecessary_cast)] // the word is u64 on 32-bit platforms
+fn credits(word: Word) -> usize {
+    ((word & CREDIT_MASK) / CREDIT_ONE) as usize
+}
+
+/// unwrap the result of the operation that waits forever, which never fails
+#[inline]
+fn forever<R>(result: Result<R, RcuError>) -> R {
+    match result {
+        Ok(r) => r,
+        Err(_) => unreachable!("waiting forever never fails"),
+    }
+}
+
+/// Which counter a reader is registered on, together with the generation
+/// of the ptr that it reads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RefKind {
+    /// the reader count that is packed in the link
+    Inline(Word),
+    /// the side counter used when the inline counter is saturated
+    Spilled(Word),
+}
+
+/// The raw pointer that is stored in the link, `None` is the null pointer.
+/// `NonNull` is used since a null fat pointer can't be made for unsized `T`
+pub(crate) type RawPtr<T> = Option<NonNull<T>>;
+
+/// Adjust the references that are owned by the raw pointer by the given
+/// number, the raw pointer must still own a reference when it's called and
+/// the references taken by a positive number are the ones that `Clone` gives
+pub(crate) type Credit<T> = unsafe fn(NonNull<T>, isize);
+
+/// check if the pointer of `T` is a thin pointer that could be packed,
+/// fat pointers of unsized `T` are always stored in an indirect slot
+#[inline]
+const fn is_thin<T: ?Sized>() -> bool {
+    core::mem::size_of::<*const T>() == core::mem::size_of::<usize>()
+}
+
+/// compare two raw pointers by address, the metadata of fat pointers is
+/// ignored like `Arc::ptr_eq`
+#[inline]
+pub(crate) fn addr_eq<T: ?Sized>(a: RawPtr<T>, b: RawPtr<T>) -> bool {
+    a.map(NonNull::cast::<()>) == b.map(NonNull::cast::<()>)
+}
 
 #[repr(C)]
-union Ptr<T> {
+union Ptr<T: ?Sized> {
     addr: usize,
     ptr: *const T,
 }
 
-impl<T> Ptr<T> {
+impl<T: ?Sized> Ptr<T> {
     #[inline]
     const fn addr(self) -> usize {
         unsafe { self.addr }
@@ -29,153 +212,972 @@ impl<T> Ptr<T> {
     }
 }
 
+/// the address of a thin pointer that could be packed into the link, `None`
+/// if the pointer has to be stored in an indirect slot, e.g. the address is
+/// not aligned or uses more than 48 bits, or it's a fat pointer of an
+/// unsized type
+#[inline]
+fn thin_addr<T: ?Sized>(ptr: RawPtr<T>) -> Option<usize> {
+    match ptr {
+        None => Some(0),
+        Some(ptr) if is_thin::<T>() => {
+            let addr = Ptr { ptr: ptr.as_ptr() }.addr();
+            fits(addr).then_some(addr)
+        }
+        Some(_) => None,
+    }
+}
+
 /// A wrapper of the pointer to the inner Arc data
-pub(crate) struct LinkWrapper<T> {
-    ptr: AtomicUsize,
+///
+/// The pointer is packed into the link word together with the reader count
+/// and the credits, the update lock and the flags of the writers are kept in
+/// a separate state word, so the reader count keeps enough bits. Pointers
+/// that can't be packed are stored in the indirect slot of their generation
+/// and the word is only marked as indirect. A slot is written by the writer before its generation is published
+/// and is not written again until the generation is retired and drained, so
+/// no address bits are needed and nothing is allocated for it.
+///
+/// Every published ptr bumps the version, the sequence of the versions is
+/// odd from the publishing until the version is bumped, so that the readers
+/// could get a consistent pair of the ptr and the version.
+///
+/// The readers are counted per generation of the ptr. A writer swaps in the
+/// new ptr at once, which starts a new generation with no readers, and only
+/// waits the readers of the retired generation before handing out the old
+/// ptr. So the readers that keep coming could never starve the writer. There
+/// are only two generations, the next writer has to wait the retired one to
+/// be drained before retiring the current one.
+///
+/// The links of the ref counted pointers also hold a batch of references
+/// that are credited to the packed ptr in advance, so that a reader takes
+/// one of them by a single atomic op on the credit bits and walks away
+/// owning a reference, without touching the ref count. Only the reader that
+/// takes the last credit refills a new batch, it pays for itself and the
+/// batch and resets the credits, the readers that come in the meantime
+/// clone the ptr under a reader, so the credits never grow beyond the batch. The batch grows from
+/// nothing for each new ptr, so a ptr that is only read a few times holds
+/// only a few credits. The writer refunds the credits that are not taken
+/// when the ptr is retired, and waits the refilling reader like a reader of
+/// the retired generation. The ptrs in the indirect slots are not credited.
+///
+/// A writer could also mutate the ptr in place under the lock when it's not
+/// shared. It takes back the credits and puts a sentinel into the reader
+/// count, the new readers run into it like a saturated count and wait the
+/// mutating flag to be cleared by the wait strategy of the cell, so a slow
+/// mutation keeps them snoozing. The version is bumped after that as if a
+/// new ptr is published.
+pub(crate) struct LinkWrapper<T: ?Sized> {
+    ptr: AtomicWord,
+    // the readers that can't be counted in the link any more, per generation
+    spilled: [AtomicUsize; 2],
+    // the inline readers of the retired generation that are not released
+    retired: AtomicUsize,
+    // the lock and the flags of the writers
+    state: AtomicUsize,
+    // twice the version, odd while a ptr is being published
+    seq: AtomicU64,
+    // the tasks that wait for a new ptr to be published
+    notifier: Notifier,
+    // the writers that are parked until the link is unlocked or drained
+    writers: Notifier,
+    // the ptrs that can't be packed, indexed by the generation
+    slots: [UnsafeCell<RawPtr<T>>; 2],
+    // how the references are credited, `None` if the ptr is not ref counted
+    credit: Option<Credit<T>>,
+    // the size of the next batch of credits, reset by each publish
+    batch: AtomicUsize,
     phantom: PhantomData<*const T>,
 }
 
-impl<T> LinkWrapper<T> {
+impl<T: ?Sized> LinkWrapper<T> {
+    /// create a link with null pointer
+    #[inline]
+    pub(crate) const fn null() -> Self {
+        Self::from_word(CREDITS_TAKEN)
+    }
+
     #[inline]
-    pub(crate) const fn new(ptr: *const T) -> Self {
-        let addr = Ptr { ptr }.addr();
-        debug_assert!(addr & LOWER_MASK == 0);
-        debug_assert!(addr & HIGHER_MASK == 0);
+    pub(crate) fn new(ptr: RawPtr<T>) -> Self {
+        let mut link = Self::from_word(0);
+        // no one else could read the link yet
+        let word = unsafe { link.encode(ptr, 0) };
+        *link.ptr.get_mut() = word;
+        link
+    }
+
+    #[inline]
+    const fn from_word(word: Word) -> Self {
         LinkWrapper {
-            ptr: AtomicUsize::new(addr << LEADING_BITS),
+            ptr: AtomicWord::new(word),
+            spilled: [AtomicUsize::new(0), AtomicUsize::new(0)],
+            retired: AtomicUsize::new(0),
+            state: AtomicUsize::new(0),
+            seq: AtomicU64::new(0),
+            notifier: Notifier::new(),
+            writers: Notifier::new(),
+            slots: [UnsafeCell::new(None), UnsafeCell::new(None)],
+            credit: None,
+            batch: AtomicUsize::new(0),
             phantom: PhantomData,
         }
     }
 
-    pub(crate) unsafe fn compare_exchange(
-        &self,
-        current: *const T,
-        new: *const T,
-        success: Ordering,
-        failure: Ordering,
-    ) -> Result<*const T, *const T> {
-        let new_addr = Ptr { ptr: new }.addr();
-        let new = new_addr << LEADING_BITS;
-
-        let old_addr = Ptr { ptr: current }.addr();
-        let old = old_addr << LEADING_BITS;
-
-        let backoff = crossbeam_utils::Backoff::new();
-        loop {
-            match self.ptr.compare_exchange(old, new, success, failure) {
-                Ok(_addr) => {
-                    // assert_eq!(old, addr);
-                    return Ok(current);
-                }
-                Err(addr) => {
-                    let addr = (addr & !REFCOUNT_MASK) >> LEADING_BITS;
-                    if addr != old {
-                        return Err(Ptr { addr }.ptr());
-                    }
-                    backoff.snooze();
-                }
+    /// credit the references of the ptr by the given function, the readers
+    /// could only take the credits by `read_credited` if it's set
+    #[inline]
+    pub(crate) const fn with_credit(mut self, credit: Option<Credit<T>>) -> Self {
+        self.credit = credit;
+        self
+    }
+
+    /// take out the pointer and leave the link null
+    #[inline]
+    pub(crate) fn take_ptr(&mut self) -> RawPtr<T> {
+        let word = core::mem::replace(self.ptr.get_mut(), CREDITS_TAKEN);
+        self.settle(word);
+        unsafe { self.decode(word) }
+    }
+
+    /// encode the ptr to the word of the generation, the ptr is stored in
+    /// the slot of the generation if it can't be packed. Only the packed
+    /// ptrs could be credited
+    ///
+    /// # Safety
+    /// the caller must be the writer that publishes the generation, and the
+    /// slot must not be used by any reader, i.e. the generation is drained
+    #[inline]
+    unsafe fn encode(&self, ptr: RawPtr<T>, gen: Word) -> Word {
+        match thin_addr(ptr) {
+            Some(addr) => pack(addr) | CREDITS_TAKEN | gen,
+            None => {
+                *self.slot(gen).get() = ptr;
+                INDIRECT | gen
             }
         }
     }
 
-    pub(crate) fn update(&self, ptr: *const T) -> *const T {
+    /// decode the ptr from the word
+    ///
+    /// # Safety
+    /// the generation of the word must be protected by a reader or the lock,
+    /// so that its slot is not written during the call
+    #[inline]
+    unsafe fn decode(&self, word: Word) -> RawPtr<T> {
+        if is_indirect(word) {
+            return *self.slot(word & GEN_MASK).get();
+        }
+        match unpack(word) {
+            // null is packed for any `T`, it's never read through the union
+            // since the metadata of a fat pointer is not initialized
+            0 => None,
+            // only thin pointers are packed directly otherwise
+            addr => NonNull::new(Ptr::<T> { addr }.ptr() as *mut T),
+        }
+    }
+
+    // the indirect slot of the generation
+    #[inline]
+    fn slot(&self, gen: Word) -> &UnsafeCell<RawPtr<T>> {
+        &self.slots[(gen != 0) as usize]
+    }
+
+    // settle the credits of the word that is retired, the credits that are
+    // not taken are refunded, the word must still own the ptr. The reader
+    // that is refilling the credits pays for itself
+    #[inline]
+    fn settle(&self, word: Word) {
+        let taken = credits(word);
+        if is_indirect(word) || taken >= CREDIT_BATCH {
+            return;
+        }
+        if let (Some(credit), Some(ptr)) = (self.credit, unsafe { self.decode(word) }) {
+            unsafe { credit(ptr, taken as isize - CREDIT_BATCH as isize) };
+        }
+    }
+
+    // the ptr is compared under the lock, a plain CAS of the word could be
+    // starved by the readers that keep changing the reader count
+    pub(crate) unsafe fn compare_exchange<W: WaitStrategy>(
+        &self,
+        current: RawPtr<T>,
+        new: RawPtr<T>,
+    ) -> Result<RawPtr<T>, RawPtr<T>> {
+        let guard = self.lock_update::<W>();
+        if !addr_eq(guard.ptr(), current) {
+            // drop the guard would release the lock
+            return Err(guard.ptr());
+        }
+        Ok(guard.unlock(new))
+    }
+
+    #[inline]
+    pub(crate) fn update<W: WaitStrategy>(&self, ptr: RawPtr<T>) -> RawPtr<T> {
+        self.lock_update::<W>().unlock(ptr)
+    }
+
+    // like `update` but give up after a bounded spin, the caller still owns
+    // `ptr` if an error is returned
+    #[inline]
+    pub(crate) fn try_update<W: WaitStrategy>(
+        &self,
+        ptr: RawPtr<T>,
+    ) -> Result<RawPtr<T>, RcuError> {
+        self.try_lock_update::<W>()?.try_unlock(ptr)
+    }
+
+    // like `update` but give up when the deadline is reached
+    #[cfg(feature = "std")]
+    #[inline]
+    pub(crate) fn update_until<W: WaitStrategy>(
+        &self,
+        ptr: RawPtr<T>,
+        deadline: Deadline,
+    ) -> Result<RawPtr<T>, RcuError> {
+        self.lock_update_until::<W>(deadline)?
+            .unlock_until(ptr, deadline)
+    }
+
+    // this is only used after lock_read, the link is still locked if an
+    // error is returned and the caller still owns `ptr`. The writers that
+    // could give up also wait the readers of the current generation before
+    // publishing, since they can't give up after that
+    fn unlock_update<W: WaitStrategy>(
+        &self,
+        ptr: RawPtr<T>,
+        wait: Wait,
+    ) -> Result<RawPtr<T>, RcuError> {
         use Ordering::*;
-        let addr = Ptr { ptr }.addr();
-        debug_assert!(addr & LOWER_MASK == 0);
-        debug_assert!(addr & HIGHER_MASK == 0);
-        let new = addr << LEADING_BITS;
-        let mut old = self.ptr.load(Relaxed) & !REFCOUNT_MASK;
+        let waiter = Waiter::<W>::new();
+        let retired = &self.retired;
+        self.wait_for(
+            &waiter,
+            wait,
+            || retired.load(Acquire) & !DRAIN_WAITING == 0,
+            || retired.fetch_or(DRAIN_WAITING, AcqRel) & !DRAIN_WAITING == 0,
+        )?;
 
-        let backoff = crossbeam_utils::Backoff::new();
-        // wait all reader release
-        while let Err(addr) = self.ptr.compare_exchange_weak(old, new, Release, Relaxed) {
-            old = addr & !REFCOUNT_MASK;
-            backoff.snooze();
+        // the generation is only changed by the lock holder
+        let gen = self.ptr.load(Relaxed) & GEN_MASK;
+        if !matches!(wait, Wait::Forever) {
+            self.wait_for(
+                &waiter,
+                wait,
+                || inline_refs(self.ptr.load(Acquire)) == 0,
+                // pairs with the SeqCst operations in `dec_inline`, either
+                // the last reader sees the flag or we see it released
+                || {
+                    self.state.fetch_or(WAITING, SeqCst);
+                    inline_refs(self.ptr.load(SeqCst)) == 0
+                },
+            )?;
+            let spilled = self.spilled(gen);
+            self.wait_for(
+                &waiter,
+                wait,
+                || spilled.load(Acquire) & !SPILL_WAITING == 0,
+                || spilled.fetch_or(SPILL_WAITING, AcqRel) & !SPILL_WAITING == 0,
+            )?;
         }
+        Ok(self.publish::<W>(ptr, gen))
+    }
 
-        core::sync::atomic::fence(Ordering::Acquire);
-        let addr = old >> LEADING_BITS;
-        Ptr { addr }.ptr()
+    // publish the ptr and release the lock, then wait the readers of the old
+    // generation and return the old ptr, the previous retired generation
+    // must be drained already
+    fn publish<W: WaitStrategy>(&self, ptr: RawPtr<T>, gen: Word) -> RawPtr<T> {
+        use Ordering::*;
+        self.retired.fetch_add(DRAINING, Relaxed);
+        // the new generation is drained before the lock is taken
+        let new = unsafe { self.encode(ptr, gen ^ GEN_MASK) };
+        // the sequence is odd until the version is bumped, it's seen by the
+        // readers of the new word
+        self.seq.fetch_add(1, Relaxed);
+        // the readers that still count on the old generation after the swap
+        // would be released to the retired counter
+        let prev = self.ptr.swap(new, AcqRel);
+        // the slot of the old generation could be written by the next writer
+        // once it's drained
+        let old = unsafe { self.decode(prev) };
+        self.settle(prev);
+        self.batch.store(0, Relaxed);
+        self.seq.fetch_add(1, Release);
+        self.unlock::<W>();
+        // pairs with the SeqCst operations in `inc_spilled`, either the
+        // spilled reader sees the new generation or we see the spilled
+        // reader, it also pairs with the one in `register_waker`
+        fence(SeqCst);
+        self.notifier.notify::<W>();
+        // the reader that is refilling the credits is released like an
+        // inline reader of the retired generation
+        let refilling = !is_indirect(prev) && credits(prev) > CREDIT_BATCH;
+        self.drain::<W>(gen, inline_refs(prev) + refilling as usize);
+        old
     }
 
-    // this is only used after lock_read
-    pub(crate) fn unlock_update(&self, ptr: *const T) -> *const T {
+    // wait all the readers of the retired generation to be released, `refs`
+    // is the number of the inline readers when it's retired
+    fn drain<W: WaitStrategy>(&self, gen: Word, refs: usize) {
         use Ordering::*;
-        let addr = Ptr { ptr }.addr();
-        debug_assert!(addr & LOWER_MASK == 0);
-        debug_assert!(addr & HIGHER_MASK == 0);
-        let new = addr << LEADING_BITS;
-        let mut old = self.ptr.load(Relaxed) & !UPDATE_REF_MASK | UPDTATE_MASK;
+        let retired = &self.retired;
+        // the released readers may have made it below the bias already
+        retired.fetch_add(refs, AcqRel);
+        let waiter = Waiter::<W>::new();
+        forever(self.wait_for(
+            &waiter,
+            Wait::Forever,
+            || retired.load(Acquire) & !DRAIN_WAITING == DRAINING,
+            || retired.fetch_or(DRAIN_WAITING, AcqRel) & !DRAIN_WAITING == DRAINING,
+        ));
+        let spilled = self.spilled(gen);
+        forever(self.wait_for(
+            &waiter,
+            Wait::Forever,
+            || spilled.load(Acquire) & !SPILL_WAITING == 0,
+            || spilled.fetch_or(SPILL_WAITING, AcqRel) & !SPILL_WAITING == 0,
+        ));
+        // the retired counter could be used by the next writer now
+        if retired.swap(0, AcqRel) & DRAIN_WAITING != 0 {
+            self.writers.notify::<W>();
+        }
+    }
+
+    // wait until `ready` returns true, snooze by the strategy and then park
+    // the writer. `arm` sets the waiting flag that the waker would check, and
+    // returns true if it's ready already
+    #[cfg_attr(not(feature = "std"), allow(unused_variables))]
+    fn wait_for<W, R, A>(
+        &self,
+        waiter: &Waiter<W>,
+        wait: Wait,
+        ready: R,
+        arm: A,
+    ) -> Result<(), RcuError>
+    where
+        W: WaitStrategy,
+        R: Fn() -> bool,
+        A: Fn() -> bool,
+    {
+        while !ready() {
+            match wait {
+                Wait::Try if waiter.try_snooze() => {}
+                Wait::Try => return Err(RcuError::WouldBlock),
+                _ if waiter.snooze() => {}
+                #[cfg(feature = "std")]
+                Wait::Forever => return self.park_writer::<W, _, _>(ready, arm, Deadline::NEVER),
+                #[cfg(feature = "std")]
+                Wait::Until(deadline) => return self.park_writer::<W, _, _>(ready, arm, deadline),
+                // there is no way to park without std, keep snoozing
+                #[cfg(not(feature = "std"))]
+                Wait::Forever => {}
+            }
+        }
+        Ok(())
+    }
 
-        let backoff = crossbeam_utils::Backoff::new();
-        // wait all reader release
-        while let Err(addr) = self.ptr.compare_exchange_weak(old, new, Release, Relaxed) {
-            old = addr & !UPDATE_REF_MASK | UPDTATE_MASK;
-            backoff.snooze();
+    #[cfg(feature = "std")]
+    #[cold]
+    fn park_writer<W, R, A>(&self, ready: R, arm: A, deadline: Deadline) -> Result<(), RcuError>
+    where
+        W: WaitStrategy,
+        R: Fn() -> bool,
+        A: Fn() -> bool,
+    {
+        let waker = thread_waker();
+        loop {
+            // the waker is consumed by each wake up, register it again
+            self.writers.register::<W>(&waker);
+            if arm() {
+                return Ok(());
+            }
+            deadline.park()?;
+            if ready() {
+                return Ok(());
+            }
         }
+    }
+
+    #[cold]
+    fn wake_writers<W: WaitStrategy>(&self) {
+        self.state.fetch_and(!WAITING, Ordering::AcqRel);
+        self.writers.notify::<W>();
+    }
 
-        core::sync::atomic::fence(Ordering::Acquire);
-        let addr = (old & !UPDTATE_MASK) >> LEADING_BITS;
-        Ptr { addr }.ptr()
+    // the readers are released without the strategy of the cell, they wake
+    // the writers with the default one
+    #[cold]
+    fn wake_drained(&self) {
+        self.retired.fetch_and(!DRAIN_WAITING, Ordering::AcqRel);
+        self.writers.notify::<SpinThenPark>();
+    }
+
+    #[cold]
+    fn wake_spilled(&self, gen: Word) {
+        self.spilled(gen)
+            .fetch_and(!SPILL_WAITING, Ordering::AcqRel);
+        self.writers.notify::<SpinThenPark>();
     }
 
     #[inline]
     pub(crate) fn is_none(&self) -> bool {
-        self.ptr.load(Ordering::Relaxed) & !REFCOUNT_MASK == 0
+        self.ptr.load(Ordering::Relaxed) & LINK_MASK == 0
+    }
+
+    // register the waker to be woken when a new ptr is published, the caller
+    // must check the version again after this to not miss the wake up
+    #[inline]
+    pub(crate) fn register_waker<W: WaitStrategy>(&self, waker: &Waker) {
+        self.notifier.register::<W>(waker);
+    }
+
+    /// the number of the wakers that wait for a new ptr
+    #[cfg(test)]
+    pub(crate) fn waiters(&self) -> usize {
+        self.notifier.len()
+    }
+
+    /// the number of the readers that are spilled to the side counters
+    #[cfg(test)]
+    pub(crate) fn spilled_refs(&self) -> usize {
+        let count = |c: &AtomicUsize| c.load(Ordering::Relaxed) & !SPILL_WAITING;
+        self.spilled.iter().map(count).sum()
+    }
+
+    /// the number of ptrs that have been published to the link
+    #[inline]
+    pub(crate) fn version(&self) -> u64 {
+        self.seq.load(Ordering::Acquire) >> 1
+    }
+
+    // check if any new ptr is published after the given version, the
+    // relaxed load is enough since it's only a hint to reload the ptr
+    #[inline]
+    pub(crate) fn changed_since(&self, version: u64) -> bool {
+        self.seq.load(Ordering::Relaxed) >> 1 != version
+    }
+
+    // the spilled counter of the generation
+    #[inline]
+    fn spilled(&self, gen: Word) -> &AtomicUsize {
+        &self.spilled[(gen != 0) as usize]
+    }
+
+    #[inline]
+    pub(crate) fn inc_ref<W: WaitStrategy>(&self) -> (RawPtr<T>, RefKind) {
+        match self.try_inc_ref::<W>() {
+            Ok(v) => v,
+            Err(_) => panic!("Too many references"),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn try_inc_ref<W: WaitStrategy>(&self) -> Result<(RawPtr<T>, RefKind), RcuError> {
+        let (word, kind) = self.try_inc_word::<W>()?;
+        // the word is protected by the reader count
+        let ptr = unsafe { self.decode(word) };
+        Ok((ptr, kind))
     }
 
+    // increase the reader count and return the protected word
     #[inline]
-    pub(crate) fn inc_ref(&self) -> *const T {
-        let addr = self.ptr.fetch_add(1, Ordering::Acquire);
-        let refs = addr & REFCOUNT_MASK;
-        assert!(refs < REFCOUNT_MASK, "Too many references");
-        let addr = (addr & !REFCOUNT_MASK) >> LEADING_BITS;
-        Ptr { addr }.ptr()
+    fn try_inc_word<W: WaitStrategy>(&self) -> Result<(Word, RefKind), RcuError> {
+        use Ordering::*;
+        let mut word = self.ptr.load(Relaxed);
+        // the sentinel of the mutating writer saturates the inline counter,
+        // so it's checked for free
+        while word & UPDATE_REF_MASK < SPILL_REFS {
+            match self
+                .ptr
+                .compare_exchange_weak(word, word + REF_ONE, Acquire, Relaxed)
+            {
+                Ok(_) => return Ok((word, RefKind::Inline(word & GEN_MASK))),
+                Err(w) => word = w,
+            }
+        }
+        self.inc_contended::<W>()
+    }
+
+    // the inline counter is saturated or the ptr is mutated in place
+    #[cold]
+    fn inc_contended<W: WaitStrategy>(&self) -> Result<(Word, RefKind), RcuError> {
+        if self.state.load(Ordering::Acquire) & MUTATING == 0 {
+            // back off to the side counter
+            return self.inc_spilled::<W>();
+        }
+        self.wait_mutated::<W>();
+        self.try_inc_word::<W>()
+    }
+
+    // wait until the ptr is not mutated in place, the readers never park
+    // since the flag is only held while the writer runs the closure, but
+    // they keep snoozing as long as the closure runs
+    #[cold]
+    fn wait_mutated<W: WaitStrategy>(&self) {
+        let waiter = Waiter::<W>::new();
+        while self.state.load(Ordering::Acquire) & MUTATING != 0 {
+            waiter.snooze();
+        }
+    }
+
+    #[cold]
+    fn inc_spilled<W: WaitStrategy>(&self) -> Result<(Word, RefKind), RcuError> {
+        use Ordering::*;
+        loop {
+            let gen = self.ptr.load(Relaxed) & GEN_MASK;
+            let kind = RefKind::Spilled(gen);
+            let refs = self.spilled(gen).fetch_add(1, SeqCst);
+            if refs & !SPILL_WAITING >= SPILL_WAITING >> 1 {
+                self.dec_ref(kind);
+                return Err(RcuError::TooManyReaders);
+            }
+            let addr = self.ptr.load(SeqCst);
+            let mutating = self.state.load(SeqCst) & MUTATING != 0;
+            if addr & GEN_MASK == gen && !mutating {
+                return Ok((addr, kind));
+            }
+            // the generation is retired in between, count on the new one,
+            // or the ptr is mutated in place, count on it after that
+            self.dec_ref(kind);
+            if mutating {
+                self.wait_mutated::<W>();
+            }
+        }
+    }
+
+    // take a credited reference of the packed ptr, return `None` if the ptr
+    // is not credited and the caller should clone it under a reader instead.
+    // The credit bits are checked before they are taken, so they never grow
+    // beyond the batch and the one of the refilling reader
+    #[inline]
+    pub(crate) fn read_credited(&self) -> Option<RawPtr<T>> {
+        use Ordering::*;
+        if !is_thin::<T>() || self.credit.is_none() {
+            return None;
+        }
+        let mut word = self.ptr.load(Relaxed);
+        loop {
+            // the ptrs in the indirect slots are never credited, and the
+            // credits are being refilled if they are beyond the batch
+            if is_indirect(word) || credits(word) > CREDIT_BATCH {
+                return None;
+            }
+            match self
+                .ptr
+                .compare_exchange_weak(word, word + CREDIT_ONE, Acquire, Relaxed)
+            {
+                // the packed ptr doesn't need the slot
+                Ok(_) if credits(word) < CREDIT_BATCH => {
+                    return Some(unsafe { self.decode(word) });
+                }
+                Ok(_) => return self.refill(word),
+                Err(w) => word = w,
+            }
+        }
+    }
+
+    // the reader took the last credit of `prev`, refill a new batch, the
+    // other readers clone the ptr under a reader until it's done
+    #[cold]
+    fn refill(&self, prev: Word) -> Option<RawPtr<T>> {
+        use Ordering::*;
+        const SAME: Word = LINK_MASK | GEN_MASK;
+        // the packed ptr is alive while the credit bits are above the batch,
+        // the writer waits the refilling reader before handing it out
+        let ptr = unsafe { self.decode(prev) };
+        let adjust = |n: usize, sign: isize| match (self.credit, ptr) {
+            (Some(credit), Some(ptr)) if n != 0 => unsafe { credit(ptr, n as isize * sign) },
+            _ => {}
+        };
+        // the credits are taken back by the writer that mutates the ptr in
+        // place, only reset them and clone the ptr under a reader. The flag
+        // is set before the credits are taken back, and the writer waits the
+        // reset if we took the last credit before that
+        let mutating = self.state.load(Acquire) & MUTATING != 0;
+        let batch = if mutating {
+            0
+        } else {
+            let batch = self.batch.load(Relaxed);
+            self.batch.store((batch * 2 + 1).min(CREDIT_BATCH), Relaxed);
+            // pay for the reader and the new batch
+            adjust(1 + batch, 1);
+            batch
+        };
+        let mut word = self.ptr.load(Relaxed);
+        loop {
+            if word & SAME != prev & SAME {
+                // the ptr is retired, the writer counts us as a reader of
+                // the retired generation, keep the reference we paid for
+                adjust(batch, -1);
+                self.dec_retired();
+                return (!mutating).then_some(ptr);
+            }
+            let credits = (CREDIT_BATCH - batch) as Word * CREDIT_ONE;
+            let new = (word & !CREDIT_MASK) | credits;
+            match self.ptr.compare_exchange_weak(word, new, Release, Relaxed) {
+                Ok(_) => return (!mutating).then_some(ptr),
+                Err(w) => word = w,
+            }
+        }
     }
 
     #[inline]
-    pub(crate) fn get_ref(&self) -> *const T {
+    pub(crate) fn get_ref<W: WaitStrategy>(&self) -> RawPtr<T> {
         let addr = self.ptr.load(Ordering::Acquire);
-        let addr = (addr & !REFCOUNT_MASK) >> LEADING_BITS;
-        Ptr { addr }.ptr()
+        if !is_indirect(addr) {
+            return unsafe { self.decode(addr) };
+        }
+        // protect the slot from being written
+        let (ptr, kind) = self.inc_ref::<W>();
+        self.dec_ref(kind);
+        ptr
     }
 
     #[inline]
-    pub(crate) fn dec_ref(&self) {
-        self.ptr.fetch_sub(1, Ordering::Release);
+    pub(crate) fn dec_ref(&self, kind: RefKind) {
+        match kind {
+            RefKind::Inline(gen) => self.dec_inline(gen),
+            RefKind::Spilled(gen) => {
+                let prev = self.spilled(gen).fetch_sub(1, Ordering::Release);
+                if prev == SPILL_WAITING | 1 {
+                    self.wake_spilled(gen);
+                }
+            }
+        }
     }
 
-    // read the inner Arc and increase the ref count
+    // release the inline reader of the generation, the reader is released
+    // to the retired counter if the generation is retired
+    #[inline]
+    fn dec_inline(&self, gen: Word) {
+        use Ordering::*;
+        let mut word = self.ptr.load(Relaxed);
+        loop {
+            // the generation can't be reused before all its readers are
+            // released, so a different one means it's retired
+            if word & GEN_MASK != gen {
+                return self.dec_retired();
+            }
+            match self
+                .ptr
+                .compare_exchange_weak(word, word - REF_ONE, SeqCst, Relaxed)
+            {
+                Ok(_) => break,
+                Err(w) => word = w,
+            }
+        }
+        // the last reader wakes the parked writers, pairs with the SeqCst
+        // operations of the writers that set the flag
+        if word & UPDATE_REF_MASK == REF_ONE && self.state.load(SeqCst) & WAITING != 0 {
+            self.wake_writers::<SpinThenPark>();
+        }
+    }
+
+    fn dec_retired(&self) {
+        let prev = self.retired.fetch_sub(1, Ordering::Release);
+        if prev == DRAIN_WAITING | DRAINING | 1 {
+            self.wake_drained();
+        }
+    }
+
+    // increase the reader count, the returned guard would decrease it
+    #[inline]
+    pub(crate) fn pin<W: WaitStrategy>(&self) -> ReadRef<'_, T> {
+        let (ptr, kind) = self.inc_ref::<W>();
+        ReadRef {
+            link: self,
+            ptr,
+            kind,
+        }
+    }
+
+    // like `pin` but also return the version of the pinned ptr
+    pub(crate) fn pin_versioned<W: WaitStrategy>(&self) -> (ReadRef<'_, T>, u64) {
+        use Ordering::Acquire;
+        let waiter = Waiter::<W>::new();
+        loop {
+            let seq = self.seq.load(Acquire);
+            if seq & 1 == 0 {
+                let (word, kind) = match self.try_inc_word::<W>() {
+                    Ok(v) => v,
+                    Err(_) => panic!("Too many references"),
+                };
+                let reader = ReadRef {
+                    link: self,
+                    ptr: unsafe { self.decode(word) },
+                    kind,
+                };
+                // the version matches the word only if no new ptr is
+                // published since the sequence is loaded
+                if self.seq.load(Acquire) == seq {
+                    return (reader, seq >> 1);
+                }
+            }
+            // the publishing window is short, the reader never parks
+            waiter.snooze();
+        }
+    }
+
+    // like `pin` but return an error instead of panic
+    #[inline]
+    pub(crate) fn try_pin<W: WaitStrategy>(&self) -> Result<ReadRef<'_, T>, RcuError> {
+        let (ptr, kind) = self.try_inc_ref::<W>()?;
+        Ok(ReadRef {
+            link: self,
+            ptr,
+            kind,
+        })
+    }
+
+    // read the inner Arc and set the update flag
     // to prevet other writer to update the inner Arc
+    // the returned guard would unlock the link with the old ptr if dropped
+    #[inline]
+    pub(crate) fn lock_update<W: WaitStrategy>(&self) -> UpdateGuard<'_, T, W> {
+        let word = forever(self.lock_read::<W>(Wait::Forever));
+        self.locked(word)
+    }
+
+    // like `lock_update` but give up when the deadline is reached
+    #[cfg(feature = "std")]
+    #[inline]
+    pub(crate) fn lock_update_until<W: WaitStrategy>(
+        &self,
+        deadline: Deadline,
+    ) -> Result<UpdateGuard<'_, T, W>, RcuError> {
+        let word = self.lock_read::<W>(Wait::Until(deadline))?;
+        Ok(self.locked(word))
+    }
+
+    // like `lock_update` but return an error if already locked
+    #[inline]
+    pub(crate) fn try_lock_update<W: WaitStrategy>(
+        &self,
+    ) -> Result<UpdateGuard<'_, T, W>, RcuError> {
+        use Ordering::Acquire;
+        if self.state.fetch_or(LOCKED, Acquire) & LOCKED != 0 {
+            return Err(RcuError::Locked);
+        }
+        Ok(self.locked(self.ptr.load(Acquire)))
+    }
+
+    #[inline]
+    fn locked<W: WaitStrategy>(&self, word: Word) -> UpdateGuard<'_, T, W> {
+        // the slot can't be written by others when locked
+        let ptr = unsafe { self.decode(word) };
+        UpdateGuard {
+            link: self,
+            ptr,
+            phantom: PhantomData,
+        }
+    }
+
+    // release the update flag without changing the ptr
+    #[inline]
+    fn unlock<W: WaitStrategy>(&self) {
+        let prev = self.state.fetch_and(!LOCKED, Ordering::Release);
+        if prev & WAITING != 0 {
+            self.wake_writers::<W>();
+        }
+    }
+
+    // set the update flag and return the locked word
     // should be paired used with unlock_update
     #[inline]
-    pub(crate) fn lock_read(&self) -> *const T {
+    fn lock_read<W: WaitStrategy>(&self, wait: Wait) -> Result<Word, RcuError> {
         use Ordering::*;
+        let unlocked = |state: usize| state & LOCKED == 0;
+        let waiter = Waiter::<W>::new();
+        loop {
+            // the ptr is only changed by the lock holder, the word loaded
+            // after the lock is the locked one
+            if unlocked(self.state.fetch_or(LOCKED, Acquire)) {
+                return Ok(self.ptr.load(Acquire));
+            }
+            self.wait_for(
+                &waiter,
+                wait,
+                || unlocked(self.state.load(Relaxed)),
+                || unlocked(self.state.fetch_or(WAITING, AcqRel)),
+            )?;
+        }
+    }
+}
+
+/// A registered reader of the link, the ptr would not be changed until
+/// the reader is dropped
+pub(crate) struct ReadRef<'a, T: ?Sized> {
+    link: &'a LinkWrapper<T>,
+    ptr: RawPtr<T>,
+    kind: RefKind,
+}
+
+impl<T: ?Sized> ReadRef<'_, T> {
+    /// the ptr that is protected by the reader
+    #[inline]
+    pub(crate) fn ptr(&self) -> RawPtr<T> {
+        self.ptr
+    }
+}
+
+impl<T: ?Sized> Drop for ReadRef<'_, T> {
+    #[inline]
+    fn drop(&mut self) {
+        self.link.dec_ref(self.kind);
+    }
+}
 
-        let addr = self.ptr.load(Relaxed);
-        let mut old = addr & !UPDTATE_MASK; // clear the update flag
-        let mut new = addr | UPDTATE_MASK; // set the update flag
+/// The update lock of the link
+///
+/// Dropping the guard without calling `unlock` would restore the old ptr
+/// and release the lock, so a panic in the update closure would not leave
+/// the link locked forever.
+#[must_use]
+pub(crate) struct UpdateGuard<'a, T: ?Sized, W: WaitStrategy> {
+    link: &'a LinkWrapper<T>,
+    ptr: RawPtr<T>,
+    // the strategy to wait for the readers
+    phantom: PhantomData<W>,
+}
 
-        let refs = old & UPDATE_REF_MASK;
-        assert!(refs < UPDATE_REF_MASK, "Too many references");
+impl<T: ?Sized, W: WaitStrategy> UpdateGuard<'_, T, W> {
+    /// the ptr that is locked
+    #[inline]
+    pub(crate) fn ptr(&self) -> RawPtr<T> {
+        self.ptr
+    }
 
-        let backoff = crossbeam_utils::Backoff::new();
-        while let Err(addr) = self.ptr.compare_exchange_weak(old, new, Release, Relaxed) {
-            old = addr & !UPDTATE_MASK;
-            new = addr | UPDTATE_MASK;
-            backoff.snooze();
+    /// publish the new ptr and release the lock, return the old ptr after
+    /// its readers are released
+    #[inline]
+    pub(crate) fn unlock(self, ptr: RawPtr<T>) -> RawPtr<T> {
+        forever(self.unlock_with(ptr, Wait::Forever))
+    }
+
+    /// like `unlock` but give up after a bounded spin waiting for readers,
+    /// in which case the old ptr is kept and the caller still owns `ptr`
+    #[inline]
+    pub(crate) fn try_unlock(self, ptr: RawPtr<T>) -> Result<RawPtr<T>, RcuError> {
+        self.unlock_with(ptr, Wait::Try)
+    }
+
+    /// like `try_unlock` but give up when the deadline is reached
+    #[cfg(feature = "std")]
+    #[inline]
+    pub(crate) fn unlock_until(
+        self,
+        ptr: RawPtr<T>,
+        deadline: Deadline,
+    ) -> Result<RawPtr<T>, RcuError> {
+        self.unlock_with(ptr, Wait::Until(deadline))
+    }
+
+    fn unlock_with(self, ptr: RawPtr<T>, wait: Wait) -> Result<RawPtr<T>, RcuError> {
+        // drop would release the lock if the ptr is not published
+        let old = self.link.unlock_update::<W>(ptr, wait)?;
+        core::mem::forget(self);
+        Ok(old)
+    }
+
+    /// exclude the new readers of the locked ptr so that it could be
+    /// mutated in place, return `None` if any reader is still registered.
+    /// The credits are taken back, so the references that are owned by
+    /// others are exactly the ones out of the cell
+    pub(crate) fn exclude(&self) -> Option<Exclusive<'_, T, W>> {
+        use Ordering::*;
+        let link = self.link;
+        let waiter = Waiter::<W>::new();
+        // the new readers that run into the sentinel wait the flag
+        link.state.fetch_or(MUTATING, SeqCst);
+        let mut word = link.ptr.load(Relaxed);
+        loop {
+            if inline_refs(word) != 0 {
+                // the ptr is still read, no reader waits the flag yet
+                link.state.fetch_and(!MUTATING, Release);
+                return None;
+            }
+            let direct = !is_indirect(word);
+            if direct && credits(word) > CREDIT_BATCH {
+                // a reader is refilling the credits, let it reset them
+                waiter.snooze();
+                word = link.ptr.load(Relaxed);
+                continue;
+            }
+            // the sentinel saturates the inline counter, the new readers
+            // would also run out of credits and clone the ptr under a reader
+            let mut new = word + SPILL_REFS;
+            if direct {
+                new = (new & !CREDIT_MASK) | CREDITS_TAKEN;
+            }
+            match link.ptr.compare_exchange_weak(word, new, SeqCst, Relaxed) {
+                Ok(_) => break,
+                Err(w) => word = w,
+            }
         }
+        let exclusive = Exclusive {
+            link,
+            phantom: PhantomData,
+        };
+        // the credits that are not taken are refunded
+        link.settle(word);
+        // pairs with the SeqCst operations in `inc_spilled`, either the
+        // spilled reader sees the flag or we see the spilled reader
+        fence(SeqCst);
+        let gen = word & GEN_MASK;
+        let idle = link.spilled(gen).load(Acquire) & !SPILL_WAITING == 0
+            && link.retired.load(Acquire) & !DRAIN_WAITING == 0;
+        idle.then_some(exclusive)
+    }
+}
+
+/// The new readers of the link are excluded until it's dropped, so the
+/// ptr that is not shared could be mutated in place
+#[must_use]
+pub(crate) struct Exclusive<'a, T: ?Sized, W: WaitStrategy> {
+    link: &'a LinkWrapper<T>,
+    // the strategy to wake the waiting tasks
+    phantom: PhantomData<W>,
+}
+
+impl<T: ?Sized, W: WaitStrategy> Exclusive<'_, T, W> {
+    /// the ptr is mutated, bump the version like publishing a new ptr and
+    /// let the readers in
+    pub(crate) fn publish(self) {
+        use Ordering::*;
+        let link = self.link;
+        // no reader could pin the ptr until the sentinel is removed
+        link.seq.fetch_add(2, Release);
+        // the flag is cleared by drop, the readers see the new version then
+        drop(self);
+        // pairs with the one in `register_waker`
+        fence(SeqCst);
+        link.notifier.notify::<W>();
+    }
+}
 
-        core::sync::atomic::fence(Ordering::Acquire);
+impl<T: ?Sized, W: WaitStrategy> Drop for Exclusive<'_, T, W> {
+    fn drop(&mut self) {
+        use Ordering::Release;
+        // the sentinel is removed before the flag, so the readers that see
+        // the flag cleared never run into it again
+        self.link.ptr.fetch_sub(SPILL_REFS, Release);
+        self.link.state.fetch_and(!MUTATING, Release);
+    }
+}
 
-        let addr = (old & !REFCOUNT_MASK) >> LEADING_BITS;
-        Ptr { addr }.ptr()
+impl<T: ?Sized, W: WaitStrategy> Drop for UpdateGuard<'_, T, W> {
+    fn drop(&mut self) {
+        // the ptr is not changed, no need to wait for the readers
+        self.link.unlock::<W>();
     }
 }
 
-impl<T: fmt::Debug> fmt::Debug for LinkWrapper<T> {
+// the slots are only written by the writer before the generation is
+// published, a panic never leaves a slot half written
+impl<T: ?Sized + RefUnwindSafe> RefUnwindSafe for LinkWrapper<T> {}
+
+impl<T: ?Sized + fmt::Debug> fmt::Debug for LinkWrapper<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let ptr = self.get_ref();
+        let ptr = self.get_ref::<SpinThenPark>();
         f.debug_struct("Link").field("ptr", &ptr).finish()
     }
 }
@@ -0,0 +1,58 @@
+//! The `membarrier(2)` syscall of Linux, it's only used by the `RcuDomain`
+//! that is created by `with_membarrier` under the `membarrier` feature. The
+//! syscall is issued by `libc`, the other platforms fall back to the memory
+//! fences.
+
+#[cfg(all(feature = "membarrier", target_os = "linux"))]
+mod sys {
+    use libc::{c_int, c_long, c_uint};
+
+    const MEMBARRIER_CMD_PRIVATE_EXPEDITED: c_int = 1 << 3;
+    const MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED: c_int = 1 << 4;
+
+    fn membarrier(cmd: c_int) -> c_long {
+        unsafe { libc::syscall(libc::SYS_membarrier, cmd, 0 as c_uint, 0 as c_int) }
+    }
+
+    pub(crate) fn register() -> bool {
+        register_with(membarrier)
+    }
+
+    /// register by the given syscall, it fails on the kernels before 4.14
+    /// or when the syscall is filtered, e.g. by seccomp
+    pub(crate) fn register_with(membarrier: impl Fn(c_int) -> c_long) -> bool {
+        membarrier(MEMBARRIER_CMD_REGISTER_PRIVATE_EXPEDITED) == 0
+    }
+
+    pub(crate) fn barrier() {
+        let ret = membarrier(MEMBARRIER_CMD_PRIVATE_EXPEDITED);
+        // it never fails once the process is registered
+        debug_assert_eq!(ret, 0);
+    }
+}
+
+#[cfg(not(all(feature = "membarrier", target_os = "linux")))]
+mod sys {
+    pub(crate) fn register() -> bool {
+        false
+    }
+
+    pub(crate) fn barrier() {
+        unreachable!("membarrier is not supported")
+    }
+}
+
+#[cfg(all(test, feature = "membarrier", target_os = "linux"))]
+pub(crate) use sys::register_with;
+
+/// register the process for the expedited membarrier, return `false` if
+/// it's not supported by the platform or the kernel
+pub(crate) fn register() -> bool {
+    sys::register()
+}
+
+/// issue a full memory barrier on all the running threads of the process,
+/// the process must be registered
+pub(crate) fn barrier() {
+    sys::barrier()
+}
@@ -0,0 +1,145 @@
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, Ordering};
+use core::task::Waker;
+
+use crate::park::Waiter;
+use crate::strategy::WaitStrategy;
+
+/// The wakers that wait for the next publish, protected by a spin lock that
+/// is waited by the strategy of the caller, it's never parked since the lock
+/// is only held for a push or a take
+struct Waiters {
+    locked: AtomicBool,
+    wakers: UnsafeCell<Vec<Waker>>,
+}
+
+impl Waiters {
+    #[inline]
+    fn with<W: WaitStrategy, R>(&self, f: impl FnOnce(&mut Vec<Waker>) -> R) -> R {
+        let waiter = Waiter::<W>::new();
+        while self.locked.swap(true, Ordering::Acquire) {
+            waiter.snooze();
+        }
+        let ret = f(unsafe { &mut *self.wakers.get() });
+        self.locked.store(false, Ordering::Release);
+        ret
+    }
+}
+
+/// The notifier of the link, the waiters are allocated when the first
+/// waker is registered, so the links that nobody waits on only pay for
+/// a null check on each publish.
+pub(crate) struct Notifier {
+    waiters: AtomicPtr<Waiters>,
+}
+
+impl Notifier {
+    #[inline]
+    pub(crate) const fn new() -> Self {
+        Notifier {
+            waiters: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    fn waiters(&self) -> &Waiters {
+        let mut waiters = self.waiters.load(Ordering::Acquire);
+        if waiters.is_null() {
+            let new = Box::into_raw(Box::new(Waiters {
+                locked: AtomicBool::new(false),
+                wakers: UnsafeCell::new(Vec::new()),
+            }));
+            match self.waiters.compare_exchange(
+                ptr::null_mut(),
+                new,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => waiters = new,
+                Err(cur) => {
+                    drop(unsafe { Box::from_raw(new) });
+                    waiters = cur;
+                }
+            }
+        }
+        unsafe { &*waiters }
+    }
+
+    /// register the waker to be woken by the next `notify`, the caller must
+    /// check the state again after this to not miss the notification
+    pub(crate) fn register<W: WaitStrategy>(&self, waker: &Waker) {
+        self.waiters().with::<W, _>(|wakers| {
+            if !wakers.iter().any(|w| w.will_wake(waker)) {
+                wakers.push(waker.clone());
+            }
+        });
+        // pairs with the fence before `notify`, either the waker is found
+        // by the notifier or the caller sees the new state
+        core::sync::atomic::fence(Ordering::SeqCst);
+    }
+
+    /// wake all the registered wakers, the caller must issue a `SeqCst`
+    /// fence after the state is changed
+    #[inline]
+    pub(crate) fn notify<W: WaitStrategy>(&self) {
+        let waiters = self.waiters.load(Ordering::Acquire);
+        if !waiters.is_null() {
+            Self::wake_all::<W>(unsafe { &*waiters });
+        }
+    }
+
+    /// the number of the registered wakers
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        let waiters = self.waiters.load(Ordering::Acquire);
+        if waiters.is_null() {
+            return 0;
+        }
+        unsafe { &*waiters }.with::<crate::strategy::Spin, _>(|wakers| wakers.len())
+    }
+
+    #[cold]
+    fn wake_all<W: WaitStrategy>(waiters: &Waiters) {
+        let wakers = waiters.with::<W, _>(core::mem::take);
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+impl Drop for Notifier {
+    fn drop(&mut self) {
+        let waiters = *self.waiters.get_mut();
+        if !waiters.is_null() {
+            drop(unsafe { Box::from_raw(waiters) });
+        }
+    }
+}
+
+/// The waker that unparks the current thread, the same waker is returned on
+/// each call so that the waits that time out register it again instead of
+/// piling up new wakers in the notifier
+#[cfg(feature = "std")]
+pub(crate) fn thread_waker() -> Waker {
+    struct ThreadWaker(std::thread::Thread);
+
+    impl std::task::Wake for ThreadWaker {
+        fn wake(self: alloc::sync::Arc<Self>) {
+            self.0.unpark();
+        }
+
+        fn wake_by_ref(self: &alloc::sync::Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn new_waker() -> Waker {
+        Waker::from(alloc::sync::Arc::new(ThreadWaker(std::thread::current())))
+    }
+
+    std::thread_local! {
+        static WAKER: Waker = new_waker();
+    }
+    // the thread local may be destroyed already when called in a destructor
+    WAKER.try_with(Waker::clone).unwrap_or_else(|_| new_waker())
+}
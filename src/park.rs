@@ -0,0 +1,87 @@
+use core::cell::Cell;
+use core::marker::PhantomData;
+
+#[cfg(feature = "std")]
+use crate::error::RcuError;
+use crate::strategy::{WaitStrategy, TRY_LIMIT};
+
+/// The deadline of a blocking operation
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Deadline {
+    at: Option<std::time::Instant>,
+}
+
+#[cfg(feature = "std")]
+impl Deadline {
+    /// wait forever
+    pub(crate) const NEVER: Deadline = Deadline { at: None };
+
+    /// the deadline after the timeout from now, the timeout that is too
+    /// long to be represented means forever
+    #[inline]
+    pub(crate) fn after(timeout: std::time::Duration) -> Self {
+        Deadline {
+            at: std::time::Instant::now().checked_add(timeout),
+        }
+    }
+
+    /// park the current thread until it's unparked or the deadline is
+    /// reached, return `Timeout` if the deadline is already passed
+    pub(crate) fn park(&self) -> Result<(), RcuError> {
+        match self.at {
+            None => std::thread::park(),
+            Some(at) => {
+                let now = std::time::Instant::now();
+                if now >= at {
+                    return Err(RcuError::Timeout);
+                }
+                std::thread::park_timeout(at - now);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How long a blocked operation would wait
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Wait {
+    /// never give up, the thread is parked when the strategy says so under
+    /// the std feature, or keeps snoozing without it
+    Forever,
+    /// give up after a bounded number of rounds
+    Try,
+    /// snooze for a while and then park until the deadline
+    #[cfg(feature = "std")]
+    Until(Deadline),
+}
+
+/// The rounds of a blocked thread, each one is waited by the strategy `W`
+pub(crate) struct Waiter<W> {
+    step: Cell<u32>,
+    phantom: PhantomData<W>,
+}
+
+impl<W: WaitStrategy> Waiter<W> {
+    #[inline]
+    pub(crate) fn new() -> Self {
+        Waiter {
+            step: Cell::new(0),
+            phantom: PhantomData,
+        }
+    }
+
+    /// wait one round, return `false` if the thread should be parked
+    #[inline]
+    pub(crate) fn snooze(&self) -> bool {
+        let step = self.step.get();
+        self.step.set(step.saturating_add(1));
+        W::snooze(step)
+    }
+
+    /// like `snooze` but return `false` if the thread should give up
+    #[inline]
+    pub(crate) fn try_snooze(&self) -> bool {
+        self.step.get() < TRY_LIMIT && self.snooze()
+    }
+}
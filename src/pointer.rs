@@ -0,0 +1,197 @@
+use alloc::boxed::Box;
+use alloc::sync::{Arc, Weak};
+use core::ptr::NonNull;
+
+/// A pointer type that could be stored in a [`RcuCellOf`](crate::RcuCellOf)
+///
+/// The cell only stores the raw pointer returned by `into_raw`, readers
+/// would get a new pointer by cloning the one that is rebuilt by `from_raw`
+/// while the writers are blocked, so the pointee is always valid when used.
+/// The target could be unsized, e.g. `str`, `[T]` or `dyn Trait`.
+///
+/// # Safety
+///
+/// The implementation must guarantee that:
+/// - `from_raw(into_raw(p))` gives back the same pointer `p`, the raw pointer
+///   owns whatever `p` owns and stays valid until it's passed to `from_raw`
+/// - the raw pointer is `None` only if the pointer is "empty", e.g. `None` or
+///   a dangling `Weak`, and `from_raw` must accept `None` in that case
+/// - `as_raw` returns the same value that `into_raw` would return
+/// - the `Clone` implementation, if any, gives a pointer to the same target
+///   or an equivalent copy of it
+pub unsafe trait RcuPointer: Sized {
+    /// The type that the pointer points to
+    type Target: ?Sized;
+
+    /// convert the pointer into a raw pointer, the ownership is moved to the
+    /// raw pointer
+    fn into_raw(this: Self) -> Option<NonNull<Self::Target>>;
+
+    /// rebuild the pointer from the raw pointer
+    ///
+    /// # Safety
+    /// the raw pointer must come from `into_raw` of the same type and it
+    /// could only be rebuilt once
+    unsafe fn from_raw(ptr: Option<NonNull<Self::Target>>) -> Self;
+
+    /// get the raw pointer without consuming the pointer
+    fn as_raw(this: &Self) -> Option<NonNull<Self::Target>>;
+}
+
+/// adjust the strong count of the Arc by `n`, the credits of the Arc cells
+pub(crate) unsafe fn credit_arc<T: ?Sized>(ptr: NonNull<T>, n: isize) {
+    let ptr = ptr.as_ptr() as *const T;
+    for _ in 0..n {
+        Arc::increment_strong_count(ptr);
+    }
+    for _ in n..0 {
+        Arc::decrement_strong_count(ptr);
+    }
+}
+
+unsafe impl<T: ?Sized> RcuPointer for Arc<T> {
+    type Target = T;
+
+    #[inline]
+    fn into_raw(this: Self) -> Option<NonNull<T>> {
+        NonNull::new(Arc::into_raw(this) as *mut T)
+    }
+
+    #[inline]
+    unsafe fn from_raw(ptr: Option<NonNull<T>>) -> Self {
+        Arc::from_raw(ptr.unwrap_unchecked().as_ptr())
+    }
+
+    #[inline]
+    fn as_raw(this: &Self) -> Option<NonNull<T>> {
+        // keep the provenance of the whole allocation, the ptr could be
+        // turned back into the Arc
+        NonNull::new(Arc::as_ptr(this) as *mut T)
+    }
+}
+
+unsafe impl<T: ?Sized> RcuPointer for Option<Arc<T>> {
+    type Target = T;
+
+    #[inline]
+    fn into_raw(this: Self) -> Option<NonNull<T>> {
+        this.and_then(RcuPointer::into_raw)
+    }
+
+    #[inline]
+    unsafe fn from_raw(ptr: Option<NonNull<T>>) -> Self {
+        ptr.map(|ptr| Arc::from_raw(ptr.as_ptr()))
+    }
+
+    #[inline]
+    fn as_raw(this: &Self) -> Option<NonNull<T>> {
+        this.as_ref().and_then(RcuPointer::as_raw)
+    }
+}
+
+unsafe impl<T> RcuPointer for Weak<T> {
+    type Target = T;
+
+    #[inline]
+    fn into_raw(this: Self) -> Option<NonNull<T>> {
+        // the dangling weak doesn't point to any allocation
+        if this.ptr_eq(&Weak::new()) {
+            None
+        } else {
+            NonNull::new(Weak::into_raw(this) as *mut T)
+        }
+    }
+
+    #[inline]
+    unsafe fn from_raw(ptr: Option<NonNull<T>>) -> Self {
+        match ptr {
+            Some(ptr) => Weak::from_raw(ptr.as_ptr()),
+            None => Weak::new(),
+        }
+    }
+
+    #[inline]
+    fn as_raw(this: &Self) -> Option<NonNull<T>> {
+        if this.ptr_eq(&Weak::new()) {
+            None
+        } else {
+            NonNull::new(Weak::as_ptr(this) as *mut T)
+        }
+    }
+}
+
+unsafe impl<T: ?Sized> RcuPointer for Box<T> {
+    type Target = T;
+
+    #[inline]
+    fn into_raw(this: Self) -> Option<NonNull<T>> {
+        NonNull::new(Box::into_raw(this))
+    }
+
+    #[inline]
+    unsafe fn from_raw(ptr: Option<NonNull<T>>) -> Self {
+        Box::from_raw(ptr.unwrap_unchecked().as_ptr())
+    }
+
+    #[inline]
+    fn as_raw(this: &Self) -> Option<NonNull<T>> {
+        Some(NonNull::from(&**this))
+    }
+}
+
+unsafe impl<T: ?Sized> RcuPointer for Option<Box<T>> {
+    type Target = T;
+
+    #[inline]
+    fn into_raw(this: Self) -> Option<NonNull<T>> {
+        this.and_then(RcuPointer::into_raw)
+    }
+
+    #[inline]
+    unsafe fn from_raw(ptr: Option<NonNull<T>>) -> Self {
+        ptr.map(|ptr| Box::from_raw(ptr.as_ptr()))
+    }
+
+    #[inline]
+    fn as_raw(this: &Self) -> Option<NonNull<T>> {
+        this.as_ref().and_then(RcuPointer::as_raw)
+    }
+}
+
+unsafe impl<T: ?Sized> RcuPointer for &'static T {
+    type Target = T;
+
+    #[inline]
+    fn into_raw(this: Self) -> Option<NonNull<T>> {
+        Some(NonNull::from(this))
+    }
+
+    #[inline]
+    unsafe fn from_raw(ptr: Option<NonNull<T>>) -> Self {
+        ptr.unwrap_unchecked().as_ref()
+    }
+
+    #[inline]
+    fn as_raw(this: &Self) -> Option<NonNull<T>> {
+        Some(NonNull::from(*this))
+    }
+}
+
+unsafe impl<T: ?Sized> RcuPointer for Option<&'static T> {
+    type Target = T;
+
+    #[inline]
+    fn into_raw(this: Self) -> Option<NonNull<T>> {
+        this.map(NonNull::from)
+    }
+
+    #[inline]
+    unsafe fn from_raw(ptr: Option<NonNull<T>>) -> Self {
+        ptr.map(|ptr| ptr.as_ref())
+    }
+
+    #[inline]
+    fn as_raw(this: &Self) -> Option<NonNull<T>> {
+        this.map(NonNull::from)
+    }
+}
@@ -1,91 +1,91 @@
 use alloc::sync::Arc;
 use core::mem::ManuallyDrop;
-use core::ptr;
+use core::ptr::{self, NonNull};
 use core::sync::atomic::Ordering;
+#[cfg(feature = "std")]
+use std::time::Duration;
 
-use crate::link::LinkWrapper;
-use crate::ArcPointer;
+use crate::error::{CasFailure, RcuError, WriteFailure};
+use crate::guard::RcuGuard;
+use crate::link::{addr_eq, LinkWrapper, RawPtr};
+#[cfg(feature = "std")]
+use crate::park::Deadline;
+use crate::pointer::credit_arc;
+use crate::strategy::{SpinThenPark, WaitStrategy};
+use crate::{RcuCellOf, RcuPointer};
 
 #[inline]
-fn ptr_to_arc<T>(ptr: *const T) -> Option<Arc<T>> {
-    unsafe { ArcPointer::from_raw(ptr) }
+fn ptr_to_arc<T: ?Sized>(ptr: RawPtr<T>) -> Option<Arc<T>> {
+    unsafe { RcuPointer::from_raw(ptr) }
 }
 
-/// RCU cell, it behaves like `RwLock<Option<Arc<T>>>`
-#[derive(Debug)]
-pub struct RcuCell<T> {
-    link: LinkWrapper<T>,
+#[inline]
+fn arc_to_ptr<T: ?Sized>(data: Option<Arc<T>>) -> RawPtr<T> {
+    RcuPointer::into_raw(data)
 }
 
-unsafe impl<T: Send> Send for RcuCell<T> {}
-unsafe impl<T: Send + Sync> Sync for RcuCell<T> {}
-
-impl<T> Drop for RcuCell<T> {
-    fn drop(&mut self) {
-        let ptr = self.link.get_ref();
-        let _ = ptr_to_arc(ptr);
-    }
+// the Arc cells credit the references of the value to the readers in advance
+#[inline]
+pub(crate) const fn credited<T: ?Sized>(link: LinkWrapper<T>) -> LinkWrapper<T> {
+    link.with_credit(Some(credit_arc::<T>))
 }
 
-impl<T> Default for RcuCell<T> {
+/// RCU cell, it behaves like `RwLock<Option<Arc<T>>>`
+pub type RcuCell<T, W = SpinThenPark> = RcuCellOf<Option<Arc<T>>, W>;
+
+impl<T: ?Sized> Default for RcuCell<T> {
     fn default() -> Self {
         RcuCell::none()
     }
 }
 
-impl<T> From<Arc<T>> for RcuCell<T> {
+impl<T: ?Sized> From<Arc<T>> for RcuCell<T> {
     fn from(data: Arc<T>) -> Self {
-        let arc_ptr = Arc::into_raw(data);
-        RcuCell {
-            link: LinkWrapper::new(arc_ptr),
-        }
+        RcuCell::from(Some(data))
     }
 }
 
-impl<T> From<Option<Arc<T>>> for RcuCell<T> {
+impl<T: ?Sized> From<Option<Arc<T>>> for RcuCell<T> {
     fn from(data: Option<Arc<T>>) -> Self {
-        let ptr = data.into_raw();
-        RcuCell {
-            link: LinkWrapper::new(ptr),
-        }
+        RcuCell::from_link(credited(LinkWrapper::new(arc_to_ptr(data))))
     }
 }
 
-impl<T> RcuCell<T> {
+impl<T: ?Sized> RcuCell<T> {
     /// create an empty rcu cell instance
     #[inline]
     pub const fn none() -> Self {
-        RcuCell {
-            link: LinkWrapper::new(ptr::null()),
-        }
+        RcuCell::from_link(credited(LinkWrapper::null()))
     }
 
     /// create rcu cell from a value
     #[inline]
-    pub fn some(data: T) -> Self {
-        let ptr = Arc::into_raw(Arc::new(data));
-        RcuCell {
-            link: LinkWrapper::new(ptr),
-        }
+    pub fn some(data: T) -> Self
+    where
+        T: Sized,
+    {
+        RcuCell::from(Arc::new(data))
     }
 
     /// create rcu cell from value that can be converted to Option<T>
     #[inline]
-    pub fn new(data: impl Into<Option<T>>) -> Self {
+    pub fn new(data: impl Into<Option<T>>) -> Self
+    where
+        T: Sized,
+    {
         let data = data.into();
         match data {
             Some(data) => Self::some(data),
             None => Self::none(),
         }
     }
+}
 
+impl<T: ?Sized, W: WaitStrategy> RcuCell<T, W> {
     /// convert the rcu cell to an Arc value
     #[inline]
     pub fn into_arc(self) -> Option<Arc<T>> {
-        let ptr = self.link.get_ref();
-        let ret = ptr_to_arc(ptr);
-        let _ = ManuallyDrop::new(self);
-        ret
+        self.into_pointer()
     }
 
     /// check if the rcu cell is empty
@@ -94,13 +94,6 @@ impl<T> RcuCell<T> {
         self.link.is_none()
     }
 
-    /// write an option arc value to the rcu cell and return the old value
-    #[inline]
-    pub fn set(&self, data: Option<Arc<T>>) -> Option<Arc<T>> {
-        let new_ptr = data.into_raw();
-        ptr_to_arc(self.link.update(new_ptr))
-    }
-
     /// take the value from the rcu cell, leave the rcu cell empty
     #[inline]
     pub fn take(&self) -> Option<Arc<T>> {
@@ -114,24 +107,210 @@ impl<T> RcuCell<T> {
         self.set(Some(data))
     }
 
+    /// like `take` but return an error instead of waiting for the active readers
+    #[inline]
+    pub fn try_take(&self) -> Result<Option<Arc<T>>, RcuError> {
+        self.try_set(None).map_err(|e| e.error)
+    }
+
+    /// like `write` but return an error instead of waiting for the active
+    /// readers, the value is given back in the error
+    #[inline]
+    pub fn try_write(
+        &self,
+        data: impl Into<Arc<T>>,
+    ) -> Result<Option<Arc<T>>, WriteFailure<Arc<T>>> {
+        self.try_set(Some(data.into()))
+            .map_err(|e| e.map(Option::unwrap))
+    }
+
+    /// like `write` but give up if the active readers don't release the old
+    /// value before the timeout, the value is given back in the error
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn write_timeout(
+        &self,
+        data: impl Into<Arc<T>>,
+        timeout: Duration,
+    ) -> Result<Option<Arc<T>>, WriteFailure<Arc<T>>> {
+        self.set_timeout(Some(data.into()), timeout)
+            .map_err(|e| e.map(Option::unwrap))
+    }
+
     /// Atomicly update the value with a closure and return the old value.
     /// The closure will be called with the old value and return the new value.
     /// The closure should not take too long time, internally it's use a spin
-    /// lock to prevent other writer to update the value
+    /// lock to prevent other writer to update the value. If the closure
+    /// panics, the old value is kept and the lock is released.
     pub fn update<R, F>(&self, f: F) -> Option<Arc<T>>
     where
         F: FnOnce(Option<Arc<T>>) -> Option<R>,
         R: Into<Arc<T>>,
     {
-        // increase ref count to lock the inner Arc
-        let ptr = self.link.lock_read();
-        let old_value = ptr_to_arc(ptr);
-        let new_ptr = match f(old_value.clone()) {
-            Some(data) => Arc::into_raw(data.into()),
-            None => ptr::null_mut(),
-        };
-        self.link.unlock_update(new_ptr);
-        old_value
+        // set the update flag to lock the inner Arc
+        let guard = self.link.lock_update::<W>();
+        // the old value is still owned by the cell until it's unlocked,
+        // if the closure panics the guard would restore the old value
+        let old_value = ManuallyDrop::new(ptr_to_arc(guard.ptr()));
+        let new_ptr = arc_to_ptr(f((*old_value).clone()).map(Into::into));
+        guard.unlock(new_ptr);
+        ManuallyDrop::into_inner(old_value)
+    }
+
+    /// Modify the value in place with a closure, like `Arc::make_mut`.
+    ///
+    /// If the cell is the only owner of the value and no reader is reading
+    /// it, the value is mutated in place under the update lock and the new
+    /// readers wait until the closure returns, by the `WaitStrategy` of the
+    /// cell without parking, so keep the closure short. Otherwise the value is cloned
+    /// once and the clone is mutated and published like `update`. Either
+    /// way the version is bumped. The closure is not called if the cell is
+    /// empty. If the closure panics the lock is released, but the value may
+    /// be partially modified if it's mutated in place.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rcu_cell::RcuCell;
+    ///
+    /// let cell = RcuCell::new(vec![1, 2]);
+    /// // mutated in place
+    /// assert_eq!(cell.modify(|v| v.push(3)), Some(()));
+    /// let old = cell.read().unwrap();
+    /// // cloned since the old value is still shared
+    /// let len = cell.modify(|v| {
+    ///     v.push(4);
+    ///     v.len()
+    /// });
+    /// assert_eq!(len, Some(4));
+    /// assert_eq!(*old, [1, 2, 3]);
+    /// assert_eq!(cell.read().as_deref(), Some(&vec![1, 2, 3, 4]));
+    /// ```
+    pub fn modify<R, F>(&self, f: F) -> Option<R>
+    where
+        T: Clone,
+        F: FnOnce(&mut T) -> R,
+    {
+        let guard = self.link.lock_update::<W>();
+        // the value is still owned by the cell, the guard would release the
+        // lock if the cell is empty or the closure panics
+        let mut old_value = ManuallyDrop::new(ptr_to_arc(guard.ptr())?);
+        if let Some(exclusive) = guard.exclude() {
+            if let Some(value) = Arc::get_mut(&mut old_value) {
+                let ret = f(value);
+                exclusive.publish();
+                return Some(ret);
+            }
+        }
+        let mut value = T::clone(&old_value);
+        let ret = f(&mut value);
+        guard.unlock(arc_to_ptr(Some(Arc::new(value))));
+        drop(ManuallyDrop::into_inner(old_value));
+        Some(ret)
+    }
+
+    /// like `update` but return an error instead of spinning if another
+    /// writer is updating the value or the active readers don't release the
+    /// value in time. Note that the closure may have been called when
+    /// `WouldBlock` is returned, the new value is dropped in that case.
+    pub fn try_update<R, F>(&self, f: F) -> Result<Option<Arc<T>>, RcuError>
+    where
+        F: FnOnce(Option<Arc<T>>) -> Option<R>,
+        R: Into<Arc<T>>,
+    {
+        let guard = self.link.try_lock_update::<W>()?;
+        let old_value = ManuallyDrop::new(ptr_to_arc(guard.ptr()));
+        let new_ptr = arc_to_ptr(f((*old_value).clone()).map(Into::into));
+        match guard.try_unlock(new_ptr) {
+            Ok(_) => Ok(ManuallyDrop::into_inner(old_value)),
+            Err(e) => {
+                let _ = ptr_to_arc(new_ptr);
+                Err(e)
+            }
+        }
+    }
+
+    /// like `update` but give up if the lock is not acquired or the active
+    /// readers don't release the old value before the timeout, the writer is
+    /// parked instead of spinning while waiting. The closure is not called if
+    /// the lock is not acquired in time, otherwise the new value is dropped
+    /// when `Timeout` is returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rcu_cell::{RcuCell, RcuError};
+    /// use std::time::Duration;
+    ///
+    /// let cell = RcuCell::new(1);
+    /// let guard = cell.read_guard();
+    /// let timeout = Duration::from_millis(10);
+    /// let ret = cell.update_timeout(|v| v.map(|v| *v + 1), timeout);
+    /// assert_eq!(ret.unwrap_err(), RcuError::Timeout);
+    /// drop(guard);
+    /// let old = cell.update_timeout(|v| v.map(|v| *v + 1), timeout).unwrap();
+    /// assert_eq!(old.as_deref(), Some(&1));
+    /// assert_eq!(cell.read().as_deref(), Some(&2));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn update_timeout<R, F>(&self, f: F, timeout: Duration) -> Result<Option<Arc<T>>, RcuError>
+    where
+        F: FnOnce(Option<Arc<T>>) -> Option<R>,
+        R: Into<Arc<T>>,
+    {
+        let deadline = Deadline::after(timeout);
+        let guard = self.link.lock_update_until::<W>(deadline)?;
+        let old_value = ManuallyDrop::new(ptr_to_arc(guard.ptr()));
+        let new_ptr = arc_to_ptr(f((*old_value).clone()).map(Into::into));
+        match guard.unlock_until(new_ptr, deadline) {
+            Ok(_) => Ok(ManuallyDrop::into_inner(old_value)),
+            Err(e) => {
+                let _ = ptr_to_arc(new_ptr);
+                Err(e)
+            }
+        }
+    }
+
+    /// Optimistically update the value with a closure, like
+    /// `AtomicUsize::fetch_update`.
+    ///
+    /// The closure is called with the current value without holding any
+    /// lock, then the result is published with a compare and set. If other
+    /// writers changed the value in the meantime, the closure is called again
+    /// with the new value, so it may be called several times. The closure
+    /// could return `None` to abort the update, or `Some(new)` where `new` is
+    /// the optional new value of the cell.
+    ///
+    /// Returns `Ok(old)` if the value was updated, else `Err(current)`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rcu_cell::RcuCell;
+    ///
+    /// let a = RcuCell::new(1);
+    /// let old = a.fetch_update(|v| Some(v.map(|x| **x + 1))).unwrap();
+    /// assert_eq!(old.map(|v| *v), Some(1));
+    /// assert_eq!(a.read().map(|v| *v), Some(2));
+    /// let cur = a.fetch_update(|_| None::<Option<i32>>).unwrap_err();
+    /// assert_eq!(cur.map(|v| *v), Some(2));
+    /// ```
+    pub fn fetch_update<R, F>(&self, mut f: F) -> Result<Option<Arc<T>>, Option<Arc<T>>>
+    where
+        F: FnMut(Option<&Arc<T>>) -> Option<Option<R>>,
+        R: Into<Arc<T>>,
+    {
+        let mut current = self.read();
+        loop {
+            let new = match f(current.as_ref()) {
+                Some(new) => new.map(Into::into),
+                None => return Err(current),
+            };
+            match self.compare_and_set(current.as_ref(), new) {
+                Ok(old) => return Ok(old),
+                Err(e) => current = e.current,
+            }
+        }
     }
 
     /// Stores the optional Arc ref `new` into the RcuCell if the current
@@ -150,6 +329,10 @@ impl<T> RcuCell<T> {
     /// `Relaxed`. The failure ordering can only be `SeqCst`, `Acquire` or `Relaxed`
     /// and must be equivalent to or weaker than the success ordering.
     ///
+    /// The exchange is done under the update lock of the cell, which is
+    /// always at least as strong as `AcqRel`, so the orderings are only kept
+    /// for the compatibility.
+    ///
     /// # Safety
     ///
     /// don't deref the returned pointer, it's may be dropped by other threads
@@ -176,18 +359,20 @@ impl<T> RcuCell<T> {
         failure: Ordering,
     ) -> Result<*const T, *const T>
     where
-        T: 'a,
+        T: Sized + 'a,
     {
-        let new_ptr = match new {
-            Some(data) => Arc::as_ptr(data),
-            None => ptr::null(),
-        };
+        let as_ptr = |ptr: RawPtr<T>| ptr.map_or(ptr::null(), |p| p.as_ptr() as *const T);
+        let current = NonNull::new(current as *mut T);
+        let new_ptr = new.and_then(RcuPointer::as_raw);
 
+        let _ = (success, failure);
         self.link
-            .compare_exchange(current, new_ptr, success, failure)
-            .inspect(|ptr| {
+            .compare_exchange::<W>(current, new_ptr)
+            .map(as_ptr)
+            .map_err(as_ptr)
+            .inspect(|&ptr| {
                 // drop the old arc in the rcu cell
-                let _ = ptr_to_arc(ptr);
+                let _ = ptr_to_arc(NonNull::new(ptr as *mut T));
                 // we have succeed to exchange the arc
                 if let Some(v) = new {
                     // clone and forget the arc that hold by rcu cell
@@ -196,26 +381,81 @@ impl<T> RcuCell<T> {
             })
     }
 
-    /// read out the inner Arc value
+    /// Stores `new` into the rcu cell if the current value is the same Arc
+    /// as `current`. The comparison is done by pointer, `None` is only equal
+    /// to an empty cell.
+    ///
+    /// On success the old value is returned, which is the same as `current`.
+    /// On failure the freshly observed value is returned together with the
+    /// rejected `new` value.
+    ///
+    /// This is the safe version of `compare_exchange`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rcu_cell::RcuCell;
+    /// use std::sync::Arc;
+    ///
+    /// let a = RcuCell::new(1234);
+    /// let curr = a.read();
+    /// let old = a.compare_and_set(curr.as_ref(), None).unwrap();
+    /// assert_eq!(old, curr);
+    /// let err = a.compare_and_set(curr.as_ref(), Some(Arc::new(5678))).unwrap_err();
+    /// assert!(err.current.is_none());
+    /// assert_eq!(err.new.map(|v| *v), Some(5678));
+    /// ```
+    pub fn compare_and_set(
+        &self,
+        current: Option<&Arc<T>>,
+        new: Option<Arc<T>>,
+    ) -> Result<Option<Arc<T>>, CasFailure<Option<Arc<T>>>> {
+        let current_ptr = current.and_then(RcuPointer::as_raw);
+        let new_ptr = RcuPointer::as_raw(&new);
+        loop {
+            let res = unsafe { self.link.compare_exchange::<W>(current_ptr, new_ptr) };
+            if res.is_ok() {
+                // the rcu cell now owns the new arc
+                let _ = arc_to_ptr(new);
+                return Ok(ptr_to_arc(current_ptr));
+            }
+            // `current` is kept alive by the caller, so the pointer
+            // comparison here is free from ABA problem
+            let observed = self.read();
+            if !addr_eq(RcuPointer::as_raw(&observed), current_ptr) {
+                return Err(CasFailure {
+                    current: observed,
+                    new,
+                });
+            }
+        }
+    }
+
+    /// read out a scoped guard of the inner value, return `None` if the
+    /// cell is empty. This is cheaper than `read` since there is no `Arc`
+    /// clone, but the writers of this cell would wait until the guard is
+    /// dropped.
     #[inline]
-    pub fn read(&self) -> Option<Arc<T>> {
-        let ptr = self.link.inc_ref();
-        let v = ManuallyDrop::new(ptr_to_arc(ptr));
-        let cloned = v.as_ref().cloned();
-        self.link.dec_ref();
-        core::sync::atomic::fence(Ordering::Acquire);
-        cloned
+    pub fn read_guard(&self) -> Option<RcuGuard<'_, T>> {
+        let reader = self.link.pin::<W>();
+        reader.ptr()?;
+        Some(unsafe { RcuGuard::new(reader) })
     }
 
-    /// read inner ptr and check if it is the same as the given Arc
+    /// call the closure with a reference of the inner value, this is
+    /// the closure form of `read_guard`
     #[inline]
-    pub fn arc_eq(&self, data: &Arc<T>) -> bool {
-        core::ptr::eq(self.link.get_ref(), Arc::as_ptr(data))
+    pub fn with<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(Option<&T>) -> R,
+    {
+        let guard = self.read_guard();
+        f(guard.as_deref())
     }
 
-    /// check if two RcuCell instances point to the same inner Arc
+    /// read inner ptr and check if it is the same as the given Arc
     #[inline]
-    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
-        core::ptr::eq(this.link.get_ref(), other.link.get_ref())
+    pub fn arc_eq(&self, data: &Arc<T>) -> bool {
+        addr_eq(self.link.get_ref::<W>(), RcuPointer::as_raw(data))
     }
 }
@@ -1,32 +1,29 @@
 use alloc::sync::Arc;
 use core::mem::ManuallyDrop;
-use core::ops::Deref;
-use core::ptr;
-use core::sync::atomic::Ordering;
+#[cfg(feature = "std")]
+use std::time::Duration;
 
-use crate::link::LinkWrapper;
-use crate::ArcPointer;
+use crate::error::{CasFailure, RcuError, WriteFailure};
+use crate::guard::RcuGuard;
+use crate::link::{addr_eq, LinkWrapper, RawPtr};
+#[cfg(feature = "std")]
+use crate::park::Deadline;
+use crate::rcu_cell::credited;
+use crate::strategy::{SpinThenPark, WaitStrategy};
+use crate::{RcuCellOf, RcuPointer};
 
 #[inline]
-fn ptr_to_arc<T>(ptr: *const T) -> Arc<T> {
-    unsafe { ArcPointer::from_raw(ptr) }
+fn ptr_to_arc<T: ?Sized>(ptr: RawPtr<T>) -> Arc<T> {
+    unsafe { RcuPointer::from_raw(ptr) }
 }
 
-/// RCU cell that never contains None, behaves like `RwLock<Arc<T>>`
-#[derive(Debug)]
-pub struct RcuCellNonNull<T> {
-    link: LinkWrapper<T>,
+#[inline]
+fn arc_to_ptr<T: ?Sized>(data: Arc<T>) -> RawPtr<T> {
+    RcuPointer::into_raw(data)
 }
 
-unsafe impl<T: Send> Send for RcuCellNonNull<T> {}
-unsafe impl<T: Send + Sync> Sync for RcuCellNonNull<T> {}
-
-impl<T> Drop for RcuCellNonNull<T> {
-    fn drop(&mut self) {
-        let ptr = self.link.get_ref();
-        let _ = ptr_to_arc(ptr);
-    }
-}
+/// RCU cell that never contains None, behaves like `RwLock<Arc<T>>`
+pub type RcuCellNonNull<T, W = SpinThenPark> = RcuCellOf<Arc<T>, W>;
 
 impl<T: Default> Default for RcuCellNonNull<T> {
     fn default() -> Self {
@@ -34,78 +31,227 @@ impl<T: Default> Default for RcuCellNonNull<T> {
     }
 }
 
-impl<T> From<Arc<T>> for RcuCellNonNull<T> {
+impl<T: ?Sized> From<Arc<T>> for RcuCellNonNull<T> {
     fn from(data: Arc<T>) -> Self {
-        let arc_ptr = Arc::into_raw(data);
-        RcuCellNonNull {
-            link: LinkWrapper::new(arc_ptr),
-        }
+        RcuCellNonNull::from_link(credited(LinkWrapper::new(arc_to_ptr(data))))
     }
 }
 
-impl<T> RcuCellNonNull<T> {
+impl<T: ?Sized> RcuCellNonNull<T> {
     /// create rcu cell from a value
     #[inline]
-    pub fn new(data: T) -> Self {
-        let ptr = Arc::into_raw(Arc::new(data));
-        RcuCellNonNull {
-            link: LinkWrapper::new(ptr),
-        }
+    pub fn new(data: T) -> Self
+    where
+        T: Sized,
+    {
+        RcuCellNonNull::from(Arc::new(data))
     }
+}
 
+impl<T: ?Sized, W: WaitStrategy> RcuCellNonNull<T, W> {
     /// convert the rcu cell to an Arc value
     #[inline]
     pub fn into_arc(self) -> Arc<T> {
-        let ptr = self.link.get_ref();
-        let ret = ptr_to_arc(ptr);
-        let _ = ManuallyDrop::new(self);
-        ret
+        self.into_pointer()
     }
 
     /// write a value to the rcu cell and return the old value
     #[inline]
     pub fn write(&self, data: impl Into<Arc<T>>) -> Arc<T> {
-        let data = data.into();
-        let new_ptr = Arc::into_raw(data);
-        ptr_to_arc(self.link.update(new_ptr))
+        let new_ptr = arc_to_ptr(data.into());
+        ptr_to_arc(self.link.update::<W>(new_ptr))
+    }
+
+    /// like `write` but return an error instead of waiting for the active
+    /// readers, the value is given back in the error
+    #[inline]
+    pub fn try_write(&self, data: impl Into<Arc<T>>) -> Result<Arc<T>, WriteFailure<Arc<T>>> {
+        self.try_set(data.into())
+    }
+
+    /// like `write` but give up if the active readers don't release the old
+    /// value before the timeout, the value is given back in the error
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn write_timeout(
+        &self,
+        data: impl Into<Arc<T>>,
+        timeout: Duration,
+    ) -> Result<Arc<T>, WriteFailure<Arc<T>>> {
+        self.set_timeout(data.into(), timeout)
     }
 
     /// Atomicly update the value with a closure and return the old value.
     /// The closure will be called with the old value and return the new value.
+    /// If the closure panics, the old value is kept and the lock is released.
     pub fn update<R, F>(&self, f: F) -> Arc<T>
     where
         F: FnOnce(Arc<T>) -> R,
         R: Into<Arc<T>>,
     {
-        // increase ref count to lock the inner Arc
-        let ptr = self.link.lock_read();
-        let old_value = ptr_to_arc(ptr);
-        let new_ptr = Arc::into_raw(f(old_value.clone()).into());
-        self.link.unlock_update(new_ptr);
-        old_value
+        // set the update flag to lock the inner Arc
+        let guard = self.link.lock_update::<W>();
+        // the old value is still owned by the cell until it's unlocked,
+        // if the closure panics the guard would restore the old value
+        let old_value = ManuallyDrop::new(ptr_to_arc(guard.ptr()));
+        let new_ptr = arc_to_ptr(f((*old_value).clone()).into());
+        guard.unlock(new_ptr);
+        ManuallyDrop::into_inner(old_value)
     }
 
-    /// read out the inner Arc value
+    /// Modify the value in place with a closure, like `Arc::make_mut`.
+    ///
+    /// The value is mutated in place under the update lock if it's not
+    /// shared, otherwise it's cloned once, see `RcuCell::modify`.
+    pub fn modify<R, F>(&self, f: F) -> R
+    where
+        T: Clone,
+        F: FnOnce(&mut T) -> R,
+    {
+        let guard = self.link.lock_update::<W>();
+        let mut old_value = ManuallyDrop::new(ptr_to_arc(guard.ptr()));
+        if let Some(exclusive) = guard.exclude() {
+            if let Some(value) = Arc::get_mut(&mut old_value) {
+                let ret = f(value);
+                exclusive.publish();
+                return ret;
+            }
+        }
+        let mut value = T::clone(&old_value);
+        let ret = f(&mut value);
+        guard.unlock(arc_to_ptr(Arc::new(value)));
+        drop(ManuallyDrop::into_inner(old_value));
+        ret
+    }
+
+    /// like `update` but return an error instead of spinning if another
+    /// writer is updating the value or the active readers don't release the
+    /// value in time. Note that the closure may have been called when
+    /// `WouldBlock` is returned, the new value is dropped in that case.
+    pub fn try_update<R, F>(&self, f: F) -> Result<Arc<T>, RcuError>
+    where
+        F: FnOnce(Arc<T>) -> R,
+        R: Into<Arc<T>>,
+    {
+        let guard = self.link.try_lock_update::<W>()?;
+        let old_value = ManuallyDrop::new(ptr_to_arc(guard.ptr()));
+        let new_ptr = arc_to_ptr(f((*old_value).clone()).into());
+        match guard.try_unlock(new_ptr) {
+            Ok(_) => Ok(ManuallyDrop::into_inner(old_value)),
+            Err(e) => {
+                let _ = ptr_to_arc(new_ptr);
+                Err(e)
+            }
+        }
+    }
+
+    /// like `update` but give up if the lock is not acquired or the active
+    /// readers don't release the old value before the timeout. The closure is
+    /// not called if the lock is not acquired in time, otherwise the new value
+    /// is dropped when `Timeout` is returned.
+    #[cfg(feature = "std")]
+    pub fn update_timeout<R, F>(&self, f: F, timeout: Duration) -> Result<Arc<T>, RcuError>
+    where
+        F: FnOnce(Arc<T>) -> R,
+        R: Into<Arc<T>>,
+    {
+        let deadline = Deadline::after(timeout);
+        let guard = self.link.lock_update_until::<W>(deadline)?;
+        let old_value = ManuallyDrop::new(ptr_to_arc(guard.ptr()));
+        let new_ptr = arc_to_ptr(f((*old_value).clone()).into());
+        match guard.unlock_until(new_ptr, deadline) {
+            Ok(_) => Ok(ManuallyDrop::into_inner(old_value)),
+            Err(e) => {
+                let _ = ptr_to_arc(new_ptr);
+                Err(e)
+            }
+        }
+    }
+
+    /// Optimistically update the value with a closure, like
+    /// `AtomicUsize::fetch_update`.
+    ///
+    /// The closure is called with the current value without holding any
+    /// lock, then the result is published with a compare and set. If other
+    /// writers changed the value in the meantime, the closure is called again
+    /// with the new value, so it may be called several times. The closure
+    /// could return `None` to abort the update.
+    ///
+    /// Returns `Ok(old)` if the value was updated, else `Err(current)`.
+    pub fn fetch_update<R, F>(&self, mut f: F) -> Result<Arc<T>, Arc<T>>
+    where
+        F: FnMut(&Arc<T>) -> Option<R>,
+        R: Into<Arc<T>>,
+    {
+        let mut current = self.read();
+        loop {
+            let new = match f(&current) {
+                Some(new) => new.into(),
+                None => return Err(current),
+            };
+            match self.compare_and_set(&current, new) {
+                Ok(old) => return Ok(old),
+                Err(e) => current = e.current,
+            }
+        }
+    }
+
+    /// Stores `new` into the rcu cell if the current value is the same Arc
+    /// as `current`. The comparison is done by pointer.
+    ///
+    /// On success the old value is returned, which is the same as `current`.
+    /// On failure the freshly observed value is returned together with the
+    /// rejected `new` value.
+    pub fn compare_and_set(
+        &self,
+        current: &Arc<T>,
+        new: Arc<T>,
+    ) -> Result<Arc<T>, CasFailure<Arc<T>>> {
+        let current_ptr = RcuPointer::as_raw(current);
+        let new_ptr = RcuPointer::as_raw(&new);
+        loop {
+            let res = unsafe { self.link.compare_exchange::<W>(current_ptr, new_ptr) };
+            if res.is_ok() {
+                // the rcu cell now owns the new arc
+                let _ = arc_to_ptr(new);
+                return Ok(ptr_to_arc(current_ptr));
+            }
+            // `current` is kept alive by the caller, so the pointer
+            // comparison here is free from ABA problem
+            let observed = self.read();
+            if !Arc::ptr_eq(&observed, current) {
+                return Err(CasFailure {
+                    current: observed,
+                    new,
+                });
+            }
+        }
+    }
+
+    /// read out a scoped guard of the inner value. This is cheaper than
+    /// `read` since there is no `Arc` clone, but the writers of this cell
+    /// would wait until the guard is dropped.
     #[inline]
-    pub fn read(&self) -> Arc<T> {
-        let ptr = self.link.inc_ref();
-        let v = ManuallyDrop::new(ptr_to_arc(ptr));
-        let cloned = v.deref().clone();
-        self.link.dec_ref();
-        core::sync::atomic::fence(Ordering::Acquire);
-        cloned
+    pub fn read_guard(&self) -> RcuGuard<'_, T> {
+        let reader = self.link.pin::<W>();
+        unsafe { RcuGuard::new(reader) }
     }
 
-    /// read inner ptr and check if it is the same as the given Arc
+    /// call the closure with a reference of the inner value, this is
+    /// the closure form of `read_guard`
     #[inline]
-    pub fn arc_eq(&self, data: &Arc<T>) -> bool {
-        core::ptr::eq(self.link.get_ref(), Arc::as_ptr(data))
+    pub fn with<R, F>(&self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        let guard = self.read_guard();
+        f(&guard)
     }
 
-    /// check if two RcuCellNonNull instances point to the same inner Arc
+    /// read inner ptr and check if it is the same as the given Arc
     #[inline]
-    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
-        core::ptr::eq(this.link.get_ref(), other.link.get_ref())
+    pub fn arc_eq(&self, data: &Arc<T>) -> bool {
+        addr_eq(self.link.get_ref::<W>(), RcuPointer::as_raw(data))
     }
 }
 
@@ -114,7 +260,7 @@ mod ser {
     use super::*;
     use serde::{Deserialize, Serialize};
 
-    impl<T: Serialize> Serialize for RcuCellNonNull<T> {
+    impl<T: Serialize, W: WaitStrategy> Serialize for RcuCellNonNull<T, W> {
         fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
         where
             S: serde::Serializer,
@@ -123,13 +269,13 @@ mod ser {
         }
     }
 
-    impl<'de, T: Deserialize<'de>> Deserialize<'de> for RcuCellNonNull<T> {
+    impl<'de, T: Deserialize<'de>, W: WaitStrategy> Deserialize<'de> for RcuCellNonNull<T, W> {
         fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
         where
             D: serde::Deserializer<'de>,
         {
             let value = T::deserialize(deserializer)?;
-            Ok(RcuCellNonNull::new(value))
+            Ok(RcuCellNonNull::new(value).with_strategy())
         }
     }
 }
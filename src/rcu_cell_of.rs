@@ -0,0 +1,260 @@
+use core::fmt;
+use core::marker::PhantomData;
+use core::mem::ManuallyDrop;
+use core::sync::atomic::Ordering;
+
+use crate::cache::RcuCache;
+use crate::error::{RcuError, WriteFailure};
+use crate::link::{addr_eq, LinkWrapper, RawPtr};
+#[cfg(feature = "std")]
+use crate::park::Deadline;
+use crate::pointer::RcuPointer;
+use crate::strategy::{SpinThenPark, WaitStrategy};
+use crate::subscriber::Subscriber;
+
+/// RCU cell of any [`RcuPointer`], it behaves like `RwLock<P>`
+///
+/// [`RcuCell`](crate::RcuCell), [`RcuCellNonNull`](crate::RcuCellNonNull) and
+/// [`RcuWeak`](crate::RcuWeak) are all aliases of this type, their specific
+/// APIs are implemented on the aliases. Other pointer types like `Box<T>`,
+/// `&'static T` or user defined ones could be used through the generic APIs.
+///
+/// `W` is the [`WaitStrategy`] of the blocked writers, the cells are created
+/// with [`SpinThenPark`] and could be switched by [`with_strategy`].
+///
+/// [`with_strategy`]: RcuCellOf::with_strategy
+///
+/// # Examples
+///
+/// ```
+/// use rcu_cell::RcuCellOf;
+///
+/// static A: u8 = 1;
+/// static B: u8 = 2;
+///
+/// let cell = RcuCellOf::from_pointer(&A);
+/// assert_eq!(*cell.read(), 1);
+/// let old = cell.set(&B);
+/// assert_eq!(*old, 1);
+/// assert_eq!(*cell.read(), 2);
+/// ```
+pub struct RcuCellOf<P: RcuPointer, W = SpinThenPark> {
+    pub(crate) link: LinkWrapper<P::Target>,
+    phantom: PhantomData<(P, fn() -> W)>,
+}
+
+unsafe impl<P: RcuPointer + Send, W> Send for RcuCellOf<P, W> {}
+unsafe impl<P: RcuPointer + Send + Sync, W> Sync for RcuCellOf<P, W> {}
+
+impl<P: RcuPointer, W> Drop for RcuCellOf<P, W> {
+    fn drop(&mut self) {
+        let ptr = self.link.take_ptr();
+        let _ = unsafe { P::from_raw(ptr) };
+    }
+}
+
+impl<P: RcuPointer, W> fmt::Debug for RcuCellOf<P, W>
+where
+    P::Target: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RcuCell").field("link", &self.link).finish()
+    }
+}
+
+impl<P: RcuPointer> RcuCellOf<P> {
+    /// create rcu cell from a pointer
+    #[inline]
+    pub fn from_pointer(ptr: P) -> Self {
+        Self::from_link(LinkWrapper::new(P::into_raw(ptr)))
+    }
+}
+
+impl<P: RcuPointer, W: WaitStrategy> RcuCellOf<P, W> {
+    #[inline]
+    pub(crate) const fn from_link(link: LinkWrapper<P::Target>) -> Self {
+        RcuCellOf {
+            link,
+            phantom: PhantomData,
+        }
+    }
+
+    /// switch the cell to another wait strategy, the value is kept
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rcu_cell::{RcuCell, Spin};
+    ///
+    /// static CELL: RcuCell<u8, Spin> = RcuCell::none().with_strategy();
+    /// assert!(CELL.write(1).is_none());
+    /// assert_eq!(CELL.read().as_deref(), Some(&1));
+    /// ```
+    #[inline]
+    pub const fn with_strategy<V: WaitStrategy>(self) -> RcuCellOf<P, V> {
+        let this = ManuallyDrop::new(self);
+        // the link is moved out of the forgotten cell
+        let cell = &this as *const ManuallyDrop<Self> as *const Self;
+        RcuCellOf::from_link(unsafe { core::ptr::read(&(*cell).link) })
+    }
+
+    /// convert the rcu cell to the inner pointer
+    #[inline]
+    pub fn into_pointer(self) -> P {
+        let mut this = ManuallyDrop::new(self);
+        let ptr = this.link.take_ptr();
+        unsafe { P::from_raw(ptr) }
+    }
+
+    /// write a pointer to the rcu cell and return the old one
+    #[inline]
+    pub fn set(&self, data: P) -> P {
+        let new_ptr = P::into_raw(data);
+        unsafe { P::from_raw(self.link.update::<W>(new_ptr)) }
+    }
+
+    /// like `set` but return an error instead of waiting for the active
+    /// readers, `data` is given back in the error
+    #[inline]
+    pub fn try_set(&self, data: P) -> Result<P, WriteFailure<P>> {
+        let new_ptr = P::into_raw(data);
+        let res = self.link.try_update::<W>(new_ptr);
+        Self::written(res, new_ptr)
+    }
+
+    /// like `set` but give up if the active readers don't release the old
+    /// value before the timeout, the writer is parked instead of spinning
+    /// while waiting. `data` is given back in the error.
+    #[cfg(feature = "std")]
+    pub fn set_timeout(&self, data: P, timeout: std::time::Duration) -> Result<P, WriteFailure<P>> {
+        let new_ptr = P::into_raw(data);
+        let res = self
+            .link
+            .update_until::<W>(new_ptr, Deadline::after(timeout));
+        Self::written(res, new_ptr)
+    }
+
+    // rebuild the old ptr, or the new one that is still owned by the caller
+    // if it's not written
+    #[inline]
+    fn written(
+        res: Result<RawPtr<P::Target>, RcuError>,
+        new_ptr: RawPtr<P::Target>,
+    ) -> Result<P, WriteFailure<P>> {
+        match res {
+            Ok(ptr) => Ok(unsafe { P::from_raw(ptr) }),
+            Err(error) => Err(WriteFailure {
+                error,
+                new: unsafe { P::from_raw(new_ptr) },
+            }),
+        }
+    }
+
+    /// read out the inner pointer
+    ///
+    /// The `Arc` cells, i.e. [`RcuCell`](crate::RcuCell) and
+    /// [`RcuCellNonNull`](crate::RcuCellNonNull), credit a batch of up to 64
+    /// references to the value in advance and the readers take one of them
+    /// instead of cloning. So while the value is in the cell,
+    /// `Arc::strong_count` of it may be larger than the references out of
+    /// the cell by up to 64, and `Arc::get_mut` or `Arc::try_unwrap` of a
+    /// value that is read out may fail even if no one else holds it. The
+    /// credits that are not taken are given back when the value is replaced
+    /// or taken out of the cell.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rcu_cell::RcuCell;
+    /// use std::sync::Arc;
+    ///
+    /// let cell = RcuCell::new(1);
+    /// // the cell and the reader
+    /// assert_eq!(Arc::strong_count(&cell.read().unwrap()), 2);
+    /// let v: Vec<_> = (0..100).map(|_| cell.read().unwrap()).collect();
+    /// // the cell and the readers, plus the credits that are not taken
+    /// assert!(Arc::strong_count(&v[0]) >= 101);
+    /// drop(cell);
+    /// assert_eq!(Arc::strong_count(&v[0]), 100);
+    /// ```
+    #[inline]
+    pub fn read(&self) -> P
+    where
+        P: Clone,
+    {
+        // the credited ptr owns a reference already
+        if let Some(ptr) = self.link.read_credited() {
+            return unsafe { P::from_raw(ptr) };
+        }
+        let reader = self.link.pin::<W>();
+        let v = ManuallyDrop::new(unsafe { P::from_raw(reader.ptr()) });
+        let cloned = (*v).clone();
+        drop(reader);
+        core::sync::atomic::fence(Ordering::Acquire);
+        cloned
+    }
+
+    /// like `read` but return an error instead of panic if there are
+    /// too many readers
+    #[inline]
+    pub fn try_read(&self) -> Result<P, RcuError>
+    where
+        P: Clone,
+    {
+        let reader = self.link.try_pin::<W>()?;
+        let v = ManuallyDrop::new(unsafe { P::from_raw(reader.ptr()) });
+        Ok((*v).clone())
+    }
+
+    /// the version of the cell, it's increased by one every time a new
+    /// pointer is published by `set`, `write`, `take`, `update` or a
+    /// successful compare and exchange, or the value is modified in place
+    /// by `modify`. Unlike comparing the pointers, this
+    /// is free from the ABA problem when the allocation is reused.
+    #[inline]
+    pub fn version(&self) -> u64 {
+        self.link.version()
+    }
+
+    /// read out the inner pointer together with its version, the version is
+    /// the one that the pointer was published with
+    #[inline]
+    pub fn read_versioned(&self) -> (P, u64)
+    where
+        P: Clone,
+    {
+        let (reader, version) = self.link.pin_versioned::<W>();
+        let v = ManuallyDrop::new(unsafe { P::from_raw(reader.ptr()) });
+        let cloned = (*v).clone();
+        drop(reader);
+        core::sync::atomic::fence(Ordering::Acquire);
+        (cloned, version)
+    }
+
+    /// create a cached reader of the cell, see [`RcuCache`]
+    #[inline]
+    pub fn cache(&self) -> RcuCache<'_, P, W>
+    where
+        P: Clone,
+    {
+        RcuCache::new(self)
+    }
+
+    /// subscribe to the changes of the cell, see [`Subscriber`]
+    #[inline]
+    pub fn subscribe(&self) -> Subscriber<&Self> {
+        Subscriber::new(self)
+    }
+
+    /// check if the inner pointer is the same as the given one
+    #[inline]
+    pub fn pointer_eq(&self, data: &P) -> bool {
+        addr_eq(self.link.get_ref::<W>(), P::as_raw(data))
+    }
+
+    /// check if two rcu cells point to the same target
+    #[inline]
+    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
+        addr_eq(this.link.get_ref::<W>(), other.link.get_ref::<W>())
+    }
+}
@@ -0,0 +1,316 @@
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::{align_of, size_of, MaybeUninit};
+use core::ptr;
+use core::sync::atomic::{fence, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+
+use crate::park::Waiter;
+use crate::strategy::SpinThenPark;
+
+/// A type that could be copied as plain bytes
+///
+/// All the bytes of the type are initialized and none of them is part of a
+/// pointer, so the value could be copied in and out by atomic integer loads
+/// and stores, like `bytemuck::NoUninit`. It's implemented for the integers,
+/// the floats, `bool`, `char` and the arrays of them.
+///
+/// # Safety
+///
+/// The type must have no padding bytes, e.g. `(u8, u32)` has three of them,
+/// which are uninitialized and loading them is undefined behavior. And it
+/// must hold no pointers or references, their provenance is lost by the
+/// integer copy. A `#[repr(C)]` struct of such fields that leaves no gap
+/// between them is fine.
+///
+/// ```compile_fail
+/// use rcu_cell::RcuValue;
+///
+/// // the tuple has padding bytes
+/// let v = RcuValue::new((1u8, 2u32));
+/// ```
+pub unsafe trait NoUninit: Copy {}
+
+macro_rules! impl_no_uninit {
+    ($($t:ty)*) => {
+        $(unsafe impl NoUninit for $t {})*
+    };
+}
+
+impl_no_uninit!(u8 u16 u32 u64 u128 usize i8 i16 i32 i64 i128 isize f32 f64 bool char);
+
+unsafe impl<T: NoUninit, const N: usize> NoUninit for [T; N] {}
+
+/// A small `Copy` value that is stored inline behind a sequence lock
+///
+/// It's for the flags, counters and small structs that are read far more
+/// often than written, e.g. `[u64; 2]` or a `#[repr(C)]` struct. A store writes the value in
+/// place, so there is no allocation and no ref count at all. The readers
+/// copy the value out optimistically and retry if a writer changed it in the
+/// meantime, they never write the shared cache line. The writers are
+/// serialized by the odd sequence, the readers that overlap a write would
+/// spin until it's done, so keep the value small.
+///
+/// Every store bumps the version, which is the same as the one of
+/// [`RcuCellOf::version`](crate::RcuCellOf::version).
+///
+/// The value is copied in and out by relaxed atomic loads and stores of its
+/// words, or of its bytes if it's not aligned to a word, so `T` has to be
+/// [`NoUninit`].
+///
+/// # Examples
+///
+/// ```
+/// use rcu_cell::{NoUninit, RcuValue};
+///
+/// #[derive(Clone, Copy, Debug, PartialEq)]
+/// #[repr(C)]
+/// struct Stat {
+///     hits: u32,
+///     misses: u32,
+///     ratio: f64,
+/// }
+///
+/// // two `u32` fill the gap before the `f64`, so there is no padding
+/// unsafe impl NoUninit for Stat {}
+///
+/// let v = RcuValue::new(Stat { hits: 1, misses: 2, ratio: 0.5 });
+/// assert_eq!(v.load().hits, 1);
+/// let old = v.update(|s| Stat { hits: s.hits + 1, ..s });
+/// assert_eq!(old.hits, 1);
+/// assert_eq!(v.load_versioned().1, 1);
+/// ```
+pub struct RcuValue<T: NoUninit> {
+    // twice the version, odd while a writer is writing the value
+    seq: AtomicU64,
+    value: UnsafeCell<T>,
+}
+
+unsafe impl<T: NoUninit + Send> Send for RcuValue<T> {}
+unsafe impl<T: NoUninit + Send> Sync for RcuValue<T> {}
+
+impl<T: NoUninit + Default> Default for RcuValue<T> {
+    fn default() -> Self {
+        RcuValue::new(T::default())
+    }
+}
+
+impl<T: NoUninit> From<T> for RcuValue<T> {
+    fn from(value: T) -> Self {
+        RcuValue::new(value)
+    }
+}
+
+impl<T: NoUninit + fmt::Debug> fmt::Debug for RcuValue<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RcuValue")
+            .field("value", &self.load())
+            .finish()
+    }
+}
+
+impl<T: NoUninit> RcuValue<T> {
+    /// create a new value
+    #[inline]
+    pub const fn new(value: T) -> Self {
+        RcuValue {
+            seq: AtomicU64::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    /// convert to the inner value
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+
+    /// get the mutable reference of the inner value, the version is not
+    /// changed by writing through it
+    #[inline]
+    pub fn get_mut(&mut self) -> &mut T {
+        self.value.get_mut()
+    }
+
+    /// the version of the value, it's increased by one every time a new
+    /// value is stored by `store`, `update` or a successful
+    /// `compare_exchange`
+    #[inline]
+    pub fn version(&self) -> u64 {
+        self.seq.load(Ordering::Acquire) >> 1
+    }
+
+    /// load the value
+    #[inline]
+    pub fn load(&self) -> T {
+        self.load_versioned().0
+    }
+
+    /// load the value together with its version
+    pub fn load_versioned(&self) -> (T, u64) {
+        let waiter = Waiter::<SpinThenPark>::new();
+        loop {
+            let seq = self.seq.load(Ordering::Acquire);
+            if seq & 1 == 0 {
+                // the value may be torn by a writer, it's only used after
+                // the sequence is validated
+                let value = unsafe { atomic_load(self.value.get()) };
+                fence(Ordering::Acquire);
+                if self.seq.load(Ordering::Relaxed) == seq {
+                    return (unsafe { value.assume_init() }, seq >> 1);
+                }
+            }
+            // the readers never park
+            waiter.snooze();
+        }
+    }
+
+    /// store a new value
+    #[inline]
+    pub fn store(&self, value: T) {
+        self.swap(value);
+    }
+
+    /// store a new value and return the old one
+    pub fn swap(&self, value: T) -> T {
+        let seq = self.lock();
+        let old = unsafe { ptr::read(self.value.get()) };
+        self.unlock(seq, value);
+        old
+    }
+
+    /// Update the value with a closure and return the old value.
+    ///
+    /// The closure is called with the current value without blocking the
+    /// readers, then the result is stored if no other writer changed the
+    /// value in the meantime, or the closure is called again with the new
+    /// value. So it may be called several times.
+    pub fn update<F>(&self, mut f: F) -> T
+    where
+        F: FnMut(T) -> T,
+    {
+        loop {
+            let (current, version) = self.load_versioned();
+            let new = f(current);
+            if let Some(seq) = self.try_lock_at(version << 1) {
+                self.unlock(seq, new);
+                return current;
+            }
+        }
+    }
+
+    /// Stores `new` if the current value equals to `current`.
+    ///
+    /// Returns `Ok(current)` if the value was stored, else `Err(actual)`
+    /// with the value that is observed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rcu_cell::RcuValue;
+    ///
+    /// let v = RcuValue::new(1);
+    /// assert_eq!(v.compare_exchange(1, 2), Ok(1));
+    /// assert_eq!(v.compare_exchange(1, 3), Err(2));
+    /// assert_eq!(v.load(), 2);
+    /// ```
+    pub fn compare_exchange(&self, current: T, new: T) -> Result<T, T>
+    where
+        T: PartialEq,
+    {
+        loop {
+            let (actual, version) = self.load_versioned();
+            if actual != current {
+                return Err(actual);
+            }
+            if let Some(seq) = self.try_lock_at(version << 1) {
+                self.unlock(seq, new);
+                return Ok(actual);
+            }
+        }
+    }
+
+    // make the sequence odd to block the readers and other writers
+    fn lock(&self) -> u64 {
+        let waiter = Waiter::<SpinThenPark>::new();
+        loop {
+            let seq = self.seq.load(Ordering::Relaxed);
+            if seq & 1 == 0 {
+                if let Some(seq) = self.try_lock_at(seq) {
+                    return seq;
+                }
+            }
+            waiter.snooze();
+        }
+    }
+
+    // lock the value only if it's not changed since the sequence
+    #[inline]
+    fn try_lock_at(&self, seq: u64) -> Option<u64> {
+        self.seq
+            .compare_exchange(seq, seq + 1, Ordering::Acquire, Ordering::Relaxed)
+            .ok()?;
+        // the value is not written before the readers could see the odd
+        // sequence, pairs with the fence of the readers
+        fence(Ordering::Release);
+        Some(seq)
+    }
+
+    #[inline]
+    fn unlock(&self, seq: u64, value: T) {
+        unsafe { atomic_store(self.value.get(), value) };
+        self.seq.store(seq + 2, Ordering::Release);
+    }
+}
+
+// the value is copied by words if it's aligned to them, or else by bytes
+#[inline]
+const fn by_words<T>() -> bool {
+    align_of::<T>() >= align_of::<AtomicUsize>()
+}
+
+/// copy the value out by relaxed atomic loads, the copy may be torn by a
+/// racing writer but it's never a data race
+///
+/// # Safety
+/// `src` must be valid and aligned
+#[inline]
+unsafe fn atomic_load<T: NoUninit>(src: *const T) -> MaybeUninit<T> {
+    let mut value = MaybeUninit::<T>::uninit();
+    if by_words::<T>() {
+        let src = src.cast::<AtomicUsize>();
+        let dst = value.as_mut_ptr().cast::<usize>();
+        for i in 0..size_of::<T>() / size_of::<usize>() {
+            dst.add(i).write((*src.add(i)).load(Ordering::Relaxed));
+        }
+    } else {
+        let src = src.cast::<AtomicU8>();
+        let dst = value.as_mut_ptr().cast::<u8>();
+        for i in 0..size_of::<T>() {
+            dst.add(i).write((*src.add(i)).load(Ordering::Relaxed));
+        }
+    }
+    value
+}
+
+/// copy the value in by relaxed atomic stores, the readers see it after the
+/// sequence is released
+///
+/// # Safety
+/// `dst` must be valid and aligned, the caller must hold the odd sequence
+#[inline]
+unsafe fn atomic_store<T: NoUninit>(dst: *mut T, value: T) {
+    let src = ptr::addr_of!(value);
+    if by_words::<T>() {
+        let src = src.cast::<usize>();
+        let dst = dst.cast::<AtomicUsize>();
+        for i in 0..size_of::<T>() / size_of::<usize>() {
+            (*dst.add(i)).store(src.add(i).read(), Ordering::Relaxed);
+        }
+    } else {
+        let src = src.cast::<u8>();
+        let dst = dst.cast::<AtomicU8>();
+        for i in 0..size_of::<T>() {
+            (*dst.add(i)).store(src.add(i).read(), Ordering::Relaxed);
+        }
+    }
+}
@@ -1,34 +1,24 @@
 use alloc::sync::{Arc, Weak};
 use core::mem::ManuallyDrop;
-use core::ptr;
 use core::sync::atomic::Ordering;
 
-use crate::link::LinkWrapper;
+use crate::error::{RcuError, WriteFailure};
+use crate::link::{addr_eq, LinkWrapper, RawPtr};
+use crate::strategy::{SpinThenPark, WaitStrategy};
+use crate::{RcuCellOf, RcuPointer};
 
 #[inline]
-fn ptr_to_weak<T>(ptr: *const T) -> Weak<T> {
-    if ptr.is_null() {
-        Weak::new()
-    } else {
-        unsafe { Weak::from_raw(ptr) }
-    }
+fn ptr_to_weak<T>(ptr: RawPtr<T>) -> Weak<T> {
+    unsafe { RcuPointer::from_raw(ptr) }
 }
 
-/// RCU weak cell, it behaves like `RwLock<Weak<T>>`
-#[derive(Debug)]
-pub struct RcuWeak<T> {
-    link: LinkWrapper<T>,
+#[inline]
+fn weak_to_ptr<T>(data: Weak<T>) -> RawPtr<T> {
+    RcuPointer::into_raw(data)
 }
 
-unsafe impl<T: Send + Sync> Send for RcuWeak<T> {}
-unsafe impl<T: Send + Sync> Sync for RcuWeak<T> {}
-
-impl<T> Drop for RcuWeak<T> {
-    fn drop(&mut self) {
-        let ptr = self.link.get_ref();
-        let _ = ptr_to_weak(ptr);
-    }
-}
+/// RCU weak cell, it behaves like `RwLock<Weak<T>>`
+pub type RcuWeak<T, W = SpinThenPark> = RcuCellOf<Weak<T>, W>;
 
 impl<T> Default for RcuWeak<T> {
     fn default() -> Self {
@@ -38,10 +28,7 @@ impl<T> Default for RcuWeak<T> {
 
 impl<T> From<Weak<T>> for RcuWeak<T> {
     fn from(data: Weak<T>) -> Self {
-        let weak_ptr = Weak::into_raw(data);
-        RcuWeak {
-            link: LinkWrapper::new(weak_ptr),
-        }
+        RcuWeak::from_pointer(data)
     }
 }
 
@@ -49,63 +36,66 @@ impl<T> RcuWeak<T> {
     /// create an dummy rcu weak cell instance, upgrade from it will return None
     #[inline]
     pub const fn new() -> Self {
-        RcuWeak {
-            link: LinkWrapper::new(ptr::null()),
-        }
+        RcuWeak::from_link(LinkWrapper::null())
     }
+}
 
+impl<T, W: WaitStrategy> RcuWeak<T, W> {
     /// convert the rcu weak to a `Weak`` value
     #[inline]
     pub fn into_weak(self) -> Weak<T> {
-        let ptr = self.link.get_ref();
-        let ret = ptr_to_weak(ptr);
-        let _ = ManuallyDrop::new(self);
-        ret
+        self.into_pointer()
     }
 
     /// take the value from the rcu weak, leave the rcu weak with default value
     #[inline]
     pub fn take(&self) -> Weak<T> {
-        ptr_to_weak(self.link.update(ptr::null()))
+        ptr_to_weak(self.link.update::<W>(None))
     }
 
     /// write a new weak value to the rcu weak cell and return the old value
     #[inline]
     pub fn write(&self, data: Weak<T>) -> Weak<T> {
-        let new_ptr = if data.ptr_eq(&Weak::new()) {
-            ptr::null()
-        } else {
-            Weak::into_raw(data)
-        };
-        ptr_to_weak(self.link.update(new_ptr))
+        let new_ptr = weak_to_ptr(data);
+        ptr_to_weak(self.link.update::<W>(new_ptr))
     }
 
     /// write a new `Weak` value downgrade from the `Arc`` to the cell and return the old value
     #[inline]
     pub fn write_arc(&self, data: &Arc<T>) -> Weak<T> {
-        let weak = Arc::downgrade(data);
-        let new_ptr = Weak::into_raw(weak);
-        ptr_to_weak(self.link.update(new_ptr))
+        let new_ptr = weak_to_ptr(Arc::downgrade(data));
+        ptr_to_weak(self.link.update::<W>(new_ptr))
     }
 
-    /// read out the inner weak value
+    /// like `take` but return an error instead of waiting for the active readers
     #[inline]
-    pub fn read(&self) -> Weak<T> {
-        let ptr = self.link.inc_ref();
-        let v = ManuallyDrop::new(ptr_to_weak(ptr));
-        let cloned = (*v).clone();
-        self.link.dec_ref();
-        core::sync::atomic::fence(Ordering::Acquire);
-        cloned
+    pub fn try_take(&self) -> Result<Weak<T>, RcuError> {
+        self.link.try_update::<W>(None).map(ptr_to_weak)
+    }
+
+    /// like `write` but return an error instead of waiting for the active
+    /// readers, the value is given back in the error
+    #[inline]
+    pub fn try_write(&self, data: Weak<T>) -> Result<Weak<T>, WriteFailure<Weak<T>>> {
+        self.try_set(data)
+    }
+
+    /// like `upgrade` but return an error instead of panic if there are
+    /// too many readers
+    #[inline]
+    pub fn try_upgrade(&self) -> Result<Option<Arc<T>>, RcuError> {
+        let reader = self.link.try_pin::<W>()?;
+        let v = ManuallyDrop::new(ptr_to_weak(reader.ptr()));
+        Ok(v.upgrade())
     }
 
     /// upgrade the innner weak value to an Arc value
     #[inline]
     pub fn upgrade(&self) -> Option<Arc<T>> {
-        let ptr = self.link.inc_ref();
-        let v = ManuallyDrop::new(ptr_to_weak(ptr));
+        let reader = self.link.pin::<W>();
+        let v = ManuallyDrop::new(ptr_to_weak(reader.ptr()));
         let cloned = v.upgrade();
-        self.link.dec_ref();
+        drop(reader);
         core::sync::atomic::fence(Ordering::Acquire);
         cloned
     }
@@ -113,18 +103,12 @@ impl<T> RcuWeak<T> {
     /// read inner ptr and check if it is the same as the given Arc
     #[inline]
     pub fn arc_eq(&self, data: &Arc<T>) -> bool {
-        core::ptr::eq(self.link.get_ref(), Arc::as_ptr(data))
+        addr_eq(self.link.get_ref::<W>(), RcuPointer::as_raw(data))
     }
 
     /// read inner ptr and check if it is the same as the given Weak
     #[inline]
     pub fn weak_eq(&self, data: &Weak<T>) -> bool {
-        core::ptr::eq(self.link.get_ref(), Weak::as_ptr(data))
-    }
-
-    /// check if two RcuWeak instances point to the same inner Weak
-    #[inline]
-    pub fn ptr_eq(this: &Self, other: &Self) -> bool {
-        core::ptr::eq(this.link.get_ref(), other.link.get_ref())
+        addr_eq(self.link.get_ref::<W>(), RcuPointer::as_raw(data))
     }
 }
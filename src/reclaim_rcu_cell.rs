@@ -0,0 +1,391 @@
+use alloc::sync::Arc;
+use core::fmt;
+use core::marker::PhantomData;
+use core::ops::Deref;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Mutex, MutexGuard, TryLockError};
+
+use crate::error::{CasFailure, RcuError, WriteFailure};
+
+#[inline]
+fn arc_to_ptr<T>(data: Option<Arc<T>>) -> *mut T {
+    data.map_or(ptr::null_mut(), |v| Arc::into_raw(v) as *mut T)
+}
+
+/// # Safety
+/// the ptr must be protected by the strategy or the lock
+#[inline]
+unsafe fn clone_ptr<T>(ptr: *mut T) -> Option<Arc<T>> {
+    if ptr.is_null() {
+        return None;
+    }
+    Arc::increment_strong_count(ptr);
+    Some(Arc::from_raw(ptr))
+}
+
+pub(crate) mod sealed {
+    use super::*;
+
+    /// How the values that are swapped out of the cell are reclaimed, the
+    /// cell owns the ptr and the lock of the writers, the strategy only
+    /// protects the loaded ptr and decides when the old value is released
+    pub trait Strategy<T>: Sized {
+        /// the name of the cell in the `Debug` output
+        const NAME: &'static str;
+
+        /// the state that is kept under the lock of the writers
+        type Retired;
+
+        /// keeps the ptr that is loaded by `protect` alive
+        type Protect<'a>
+        where
+            Self: 'a;
+
+        /// create the strategy and the writer state of a new cell
+        fn create() -> (Self, Self::Retired);
+
+        /// load the ptr and protect it from being reclaimed
+        fn protect<'a>(&'a self, src: &AtomicPtr<T>) -> (*mut T, Self::Protect<'a>);
+
+        /// the old value is just swapped out under the lock, return a
+        /// reference to it that is owned by the caller
+        fn reclaim(&self, retired: &mut Self::Retired, old: Option<Arc<T>>) -> Option<Arc<T>>;
+
+        /// release the lock of the writers
+        #[inline]
+        fn unlock(retired: MutexGuard<'_, Self::Retired>) {
+            drop(retired);
+        }
+    }
+}
+
+/// The reclamation strategy of a [`ReclaimRcuCell`]
+///
+/// It's sealed and implemented by [`EpochReclaim`](crate::EpochReclaim),
+/// [`HazardReclaim`](crate::HazardReclaim) and
+/// [`StripedReclaim`](crate::StripedReclaim).
+pub trait Reclaim<T>: sealed::Strategy<T> {}
+
+impl<T, R: sealed::Strategy<T>> Reclaim<T> for R {}
+
+/// RCU cell that behaves like `RwLock<Option<Arc<T>>>`, the values that are
+/// swapped out are reclaimed by the strategy `R`
+///
+/// The writers are serialized by a lock and only differ in how the old value
+/// is released, so it's used through the aliases of the strategies,
+/// [`EpochRcuCell`](crate::EpochRcuCell),
+/// [`HazardRcuCell`](crate::HazardRcuCell) and
+/// [`StripedRcuCell`](crate::StripedRcuCell).
+pub struct ReclaimRcuCell<T, R: Reclaim<T>> {
+    ptr: AtomicPtr<T>,
+    // the lock of the writers that also keeps the retired values
+    lock: Mutex<R::Retired>,
+    strategy: R,
+    phantom: PhantomData<Arc<T>>,
+}
+
+impl<T, R: Reclaim<T>> Drop for ReclaimRcuCell<T, R> {
+    fn drop(&mut self) {
+        // no guard could be alive, the retired values are owned by the
+        // strategy
+        let ptr = *self.ptr.get_mut();
+        if !ptr.is_null() {
+            drop(unsafe { Arc::from_raw(ptr) });
+        }
+    }
+}
+
+impl<T, R: Reclaim<T>> Default for ReclaimRcuCell<T, R> {
+    fn default() -> Self {
+        ReclaimRcuCell::none()
+    }
+}
+
+impl<T, R: Reclaim<T>> From<Arc<T>> for ReclaimRcuCell<T, R> {
+    fn from(data: Arc<T>) -> Self {
+        ReclaimRcuCell::from(Some(data))
+    }
+}
+
+impl<T, R: Reclaim<T>> From<Option<Arc<T>>> for ReclaimRcuCell<T, R> {
+    fn from(data: Option<Arc<T>>) -> Self {
+        let (strategy, retired) = R::create();
+        ReclaimRcuCell {
+            ptr: AtomicPtr::new(arc_to_ptr(data)),
+            lock: Mutex::new(retired),
+            strategy,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<T: fmt::Debug, R: Reclaim<T>> fmt::Debug for ReclaimRcuCell<T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct(R::NAME)
+            .field("value", &self.read_guard().as_deref())
+            .finish()
+    }
+}
+
+impl<T, R: Reclaim<T>> ReclaimRcuCell<T, R> {
+    /// create an empty rcu cell instance
+    #[inline]
+    pub fn none() -> Self {
+        ReclaimRcuCell::from(None)
+    }
+
+    /// create rcu cell from a value
+    #[inline]
+    pub fn some(data: T) -> Self {
+        ReclaimRcuCell::from(Arc::new(data))
+    }
+
+    /// create rcu cell from value that can be converted to `Option<T>`
+    #[inline]
+    pub fn new(data: impl Into<Option<T>>) -> Self {
+        match data.into() {
+            Some(data) => Self::some(data),
+            None => Self::none(),
+        }
+    }
+
+    /// convert the rcu cell to an Arc value
+    #[inline]
+    pub fn into_arc(self) -> Option<Arc<T>> {
+        let ptr = self.ptr.swap(ptr::null_mut(), Ordering::Relaxed);
+        ptr::NonNull::new(ptr).map(|p| unsafe { Arc::from_raw(p.as_ptr()) })
+    }
+
+    /// check if the rcu cell is empty
+    #[inline]
+    pub fn is_none(&self) -> bool {
+        self.ptr.load(Ordering::Relaxed).is_null()
+    }
+
+    /// read out the inner Arc value
+    #[inline]
+    pub fn read(&self) -> Option<Arc<T>> {
+        let (ptr, _protect) = self.strategy.protect(&self.ptr);
+        // the value can't be freed while it's protected
+        unsafe { clone_ptr(ptr) }
+    }
+
+    /// read out a scoped guard of the inner value, return `None` if the
+    /// cell is empty. Whether the writers wait for the guard depends on the
+    /// strategy, see the guard of each cell
+    #[inline]
+    pub fn read_guard(&self) -> Option<ReclaimGuard<'_, T, R>> {
+        let (ptr, protect) = self.strategy.protect(&self.ptr);
+        let value = unsafe { ptr.as_ref()? };
+        Some(ReclaimGuard {
+            value,
+            _protect: protect,
+        })
+    }
+
+    /// call the closure with a reference of the inner value, this is
+    /// the closure form of `read_guard`
+    #[inline]
+    pub fn with<F, U>(&self, f: F) -> U
+    where
+        F: FnOnce(Option<&T>) -> U,
+    {
+        let guard = self.read_guard();
+        f(guard.as_deref())
+    }
+
+    /// read inner ptr and check if it is the same as the given Arc
+    #[inline]
+    pub fn arc_eq(&self, data: &Arc<T>) -> bool {
+        ptr::eq(self.ptr.load(Ordering::Acquire), Arc::as_ptr(data))
+    }
+
+    /// take the value from the rcu cell, leave the rcu cell empty
+    #[inline]
+    pub fn take(&self) -> Option<Arc<T>> {
+        self.set(None)
+    }
+
+    /// write a value to the rcu cell and return the old value
+    #[inline]
+    pub fn write(&self, data: impl Into<Arc<T>>) -> Option<Arc<T>> {
+        self.set(Some(data.into()))
+    }
+
+    /// like `take` but return `Locked` instead of waiting for other writers
+    #[inline]
+    pub fn try_take(&self) -> Result<Option<Arc<T>>, RcuError> {
+        self.try_set(None).map_err(|e| e.error)
+    }
+
+    /// like `write` but return `Locked` instead of waiting for other writers,
+    /// the value is given back in the error
+    #[inline]
+    pub fn try_write(
+        &self,
+        data: impl Into<Arc<T>>,
+    ) -> Result<Option<Arc<T>>, WriteFailure<Arc<T>>> {
+        self.try_set(Some(data.into()))
+            .map_err(|e| e.map(Option::unwrap))
+    }
+
+    /// Atomicly update the value with a closure and return the old value.
+    /// The closure will be called with the old value and return the new value.
+    /// Other writers wait until the closure returns, but the readers don't.
+    /// If the closure panics, the old value is kept.
+    pub fn update<U, F>(&self, f: F) -> Option<Arc<T>>
+    where
+        F: FnOnce(Option<Arc<T>>) -> Option<U>,
+        U: Into<Arc<T>>,
+    {
+        self.update_locked(self.lock(), f)
+    }
+
+    /// like `update` but return `Locked` instead of waiting for other
+    /// writers, the closure is not called in that case
+    pub fn try_update<U, F>(&self, f: F) -> Result<Option<Arc<T>>, RcuError>
+    where
+        F: FnOnce(Option<Arc<T>>) -> Option<U>,
+        U: Into<Arc<T>>,
+    {
+        Ok(self.update_locked(self.try_lock()?, f))
+    }
+
+    /// Optimistically update the value with a closure, like
+    /// [`RcuCell::fetch_update`](crate::RcuCell::fetch_update). The closure
+    /// may be called several times if other writers changed the value in the
+    /// meantime.
+    ///
+    /// Returns `Ok(old)` if the value was updated, else `Err(current)`.
+    pub fn fetch_update<U, F>(&self, mut f: F) -> Result<Option<Arc<T>>, Option<Arc<T>>>
+    where
+        F: FnMut(Option<&Arc<T>>) -> Option<Option<U>>,
+        U: Into<Arc<T>>,
+    {
+        let mut current = self.read();
+        loop {
+            let new = match f(current.as_ref()) {
+                Some(new) => new.map(Into::into),
+                None => return Err(current),
+            };
+            match self.compare_and_set(current.as_ref(), new) {
+                Ok(old) => return Ok(old),
+                Err(e) => current = e.current,
+            }
+        }
+    }
+
+    /// Stores `new` into the rcu cell if the current value is the same Arc
+    /// as `current`, like [`RcuCell::compare_and_set`](crate::RcuCell::compare_and_set).
+    pub fn compare_and_set(
+        &self,
+        current: Option<&Arc<T>>,
+        new: Option<Arc<T>>,
+    ) -> Result<Option<Arc<T>>, CasFailure<Option<Arc<T>>>> {
+        let current_ptr = current.map_or(ptr::null(), Arc::as_ptr);
+        let mut retired = self.lock();
+        // the value is only changed under the lock
+        let ptr = self.ptr.load(Ordering::Acquire);
+        if !ptr::eq(ptr, current_ptr) {
+            return Err(CasFailure {
+                current: unsafe { clone_ptr(ptr) },
+                new,
+            });
+        }
+        let old = self.publish(&mut retired, new);
+        R::unlock(retired);
+        Ok(old)
+    }
+
+    /// the strategy of the cell
+    #[inline]
+    pub(crate) fn strategy(&self) -> &R {
+        &self.strategy
+    }
+
+    fn set(&self, data: Option<Arc<T>>) -> Option<Arc<T>> {
+        let mut retired = self.lock();
+        let old = self.publish(&mut retired, data);
+        R::unlock(retired);
+        old
+    }
+
+    fn try_set(
+        &self,
+        data: Option<Arc<T>>,
+    ) -> Result<Option<Arc<T>>, WriteFailure<Option<Arc<T>>>> {
+        let mut retired = match self.try_lock() {
+            Ok(retired) => retired,
+            Err(error) => return Err(WriteFailure { error, new: data }),
+        };
+        let old = self.publish(&mut retired, data);
+        R::unlock(retired);
+        Ok(old)
+    }
+
+    fn update_locked<U, F>(&self, mut retired: MutexGuard<'_, R::Retired>, f: F) -> Option<Arc<T>>
+    where
+        F: FnOnce(Option<Arc<T>>) -> Option<U>,
+        U: Into<Arc<T>>,
+    {
+        let current = unsafe { clone_ptr(self.ptr.load(Ordering::Acquire)) };
+        let new = f(current).map(Into::into);
+        let old = self.publish(&mut retired, new);
+        R::unlock(retired);
+        old
+    }
+
+    // the lock is not poisoned by a panic in the update closure, since the
+    // value is not changed before the closure returns
+    #[inline]
+    fn lock(&self) -> MutexGuard<'_, R::Retired> {
+        self.lock.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    #[inline]
+    fn try_lock(&self) -> Result<MutexGuard<'_, R::Retired>, RcuError> {
+        match self.lock.try_lock() {
+            Ok(retired) => Ok(retired),
+            Err(TryLockError::Poisoned(e)) => Ok(e.into_inner()),
+            Err(TryLockError::WouldBlock) => Err(RcuError::Locked),
+        }
+    }
+
+    // swap in the new value under the lock and hand the old one to the
+    // strategy. The swap pairs with the readers that protect the ptr by
+    // `SeqCst` operations
+    fn publish(&self, retired: &mut R::Retired, data: Option<Arc<T>>) -> Option<Arc<T>> {
+        let old = self.ptr.swap(arc_to_ptr(data), Ordering::SeqCst);
+        let old = ptr::NonNull::new(old).map(|p| unsafe { Arc::from_raw(p.as_ptr()) });
+        self.strategy.reclaim(retired, old)
+    }
+}
+
+/// A scoped read guard of the [`ReclaimRcuCell`] value, the value is
+/// protected by the strategy `R` until the guard is dropped
+pub struct ReclaimGuard<'a, T, R: Reclaim<T> + 'a> {
+    value: &'a T,
+    _protect: R::Protect<'a>,
+}
+
+impl<T, R: Reclaim<T>> Deref for ReclaimGuard<'_, T, R> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T: fmt::Debug, R: Reclaim<T>> fmt::Debug for ReclaimGuard<'_, T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.value, f)
+    }
+}
+
+impl<T: fmt::Display, R: Reclaim<T>> fmt::Display for ReclaimGuard<'_, T, R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.value, f)
+    }
+}
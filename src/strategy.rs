@@ -0,0 +1,103 @@
+/// How a thread waits in the blocking loops of the cells
+///
+/// The loops call `snooze` with the number of the rounds that they have
+/// waited, starting from 0, each time the state they wait for is not ready.
+/// A writer that waits forever or until a deadline is parked once `snooze`
+/// returns `false`, so returning `true` keeps the thread busy waiting. The
+/// non-blocking `try_*` operations give up after a bounded number of rounds
+/// no matter what `snooze` returns. There is no way to park without the std
+/// feature, the writers keep calling `snooze` instead.
+///
+/// The strategy is a type parameter of the cells, the default one is
+/// [`SpinThenPark`].
+///
+/// # Examples
+///
+/// ```
+/// use rcu_cell::{RcuCell, WaitStrategy};
+///
+/// // spin with a fixed number of hints and never park
+/// struct Busy;
+///
+/// impl WaitStrategy for Busy {
+///     fn snooze(_step: u32) -> bool {
+///         (0..16).for_each(|_| core::hint::spin_loop());
+///         true
+///     }
+/// }
+///
+/// let cell = RcuCell::new(1).with_strategy::<Busy>();
+/// cell.write(2);
+/// assert_eq!(cell.read().as_deref(), Some(&2));
+/// ```
+pub trait WaitStrategy {
+    /// wait a moment in the round `step`, return `false` if the thread
+    /// should be parked instead of waiting again
+    fn snooze(step: u32) -> bool;
+}
+
+// the rounds that spin with exponential backoff
+const SPIN_LIMIT: u32 = 6;
+// the rounds before the thread is parked
+const PARK_LIMIT: u32 = 10;
+
+#[inline]
+fn spin(step: u32) {
+    for _ in 0..1u32 << step.min(SPIN_LIMIT) {
+        core::hint::spin_loop();
+    }
+}
+
+/// Busy wait with exponential backoff and never park, for the latency
+/// critical threads that own a core
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Spin;
+
+impl WaitStrategy for Spin {
+    #[inline]
+    fn snooze(step: u32) -> bool {
+        spin(step);
+        true
+    }
+}
+
+/// Spin for a while and then yield the thread to the scheduler, never park
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpinThenYield;
+
+#[cfg(feature = "std")]
+impl WaitStrategy for SpinThenYield {
+    #[inline]
+    fn snooze(step: u32) -> bool {
+        if step <= SPIN_LIMIT {
+            spin(step);
+        } else {
+            std::thread::yield_now();
+        }
+        true
+    }
+}
+
+/// Spin for a while, then yield the thread for a few rounds and then park
+/// it until it's woken, the default strategy of the cells. It's like the
+/// `Backoff` of `crossbeam-utils`. Without the std feature it keeps spinning
+/// instead of yielding.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SpinThenPark;
+
+impl WaitStrategy for SpinThenPark {
+    #[inline]
+    fn snooze(step: u32) -> bool {
+        #[cfg(feature = "std")]
+        if step > SPIN_LIMIT {
+            std::thread::yield_now();
+            return step < PARK_LIMIT;
+        }
+        spin(step);
+        step < PARK_LIMIT
+    }
+}
+
+/// The rounds that a `try_*` operation waits before giving up
+pub(crate) const TRY_LIMIT: u32 = PARK_LIMIT;
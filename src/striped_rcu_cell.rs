@@ -0,0 +1,169 @@
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use core::cell::Cell;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use crossbeam_utils::CachePadded;
+
+use crate::park::Waiter;
+use crate::reclaim_rcu_cell::sealed::Strategy;
+use crate::reclaim_rcu_cell::{ReclaimGuard, ReclaimRcuCell};
+use crate::strategy::SpinThenYield;
+
+// the stripes are never more than this, no matter how many cpus there are
+const MAX_STRIPES: usize = 64;
+
+/// the number of the stripes, one per cpu rounded up to a power of two
+fn stripes() -> usize {
+    static STRIPES: AtomicUsize = AtomicUsize::new(0);
+    let n = STRIPES.load(Ordering::Relaxed);
+    if n != 0 {
+        return n;
+    }
+    let n = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .next_power_of_two()
+        .min(MAX_STRIPES);
+    STRIPES.store(n, Ordering::Relaxed);
+    n
+}
+
+/// the stripe hint of the current thread, the threads are spread over the
+/// stripes in a round robin way when they first read
+#[inline]
+fn thread_stripe() -> usize {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    std::thread_local! {
+        static STRIPE: Cell<usize> = const { Cell::new(usize::MAX) };
+    }
+    STRIPE
+        .try_with(|s| {
+            if s.get() == usize::MAX {
+                s.set(NEXT.fetch_add(1, Ordering::Relaxed) % MAX_STRIPES);
+            }
+            s.get()
+        })
+        .unwrap_or(0)
+}
+
+/// RCU cell with striped reader counters, it behaves like
+/// `RwLock<Option<Arc<T>>>` and has the same read/write/update surface as
+/// [`RcuCell`](crate::RcuCell).
+///
+/// The readers of [`RcuCell`](crate::RcuCell) all count on the link word,
+/// so the cache line of the word bounces between the cpus when many of them
+/// read at the same time. Here the reader counts are spread over cache
+/// padded stripes, one per cpu, and each thread always counts on the same
+/// stripe, so the readers on different cpus don't share any written cache
+/// line.
+///
+/// The counters of each stripe are split into two indexes. The readers count
+/// on the current index, a writer swaps in the new value, flips the index and
+/// waits the old index of all the stripes to be drained before handing out
+/// the old value, so the readers that keep coming could never starve it. The
+/// writers are serialized by a lock and pay for scanning all the stripes,
+/// this cell is for the data that is read far more often than written.
+///
+/// # Examples
+///
+/// ```
+/// use rcu_cell::StripedRcuCell;
+///
+/// let cell = StripedRcuCell::new(1);
+/// assert_eq!(cell.with(|v| v.copied()), Some(1));
+/// let old = cell.update(|v| v.map(|v| *v + 1));
+/// assert_eq!(old.as_deref(), Some(&1));
+/// assert_eq!(*cell.read_guard().unwrap(), 2);
+/// ```
+pub type StripedRcuCell<T> = ReclaimRcuCell<T, StripedReclaim>;
+
+/// A scoped read guard of the [`StripedRcuCell`] value.
+///
+/// The writers of the same cell would wait for the guard before returning
+/// the old value, so don't hold it for too long time and never write to the
+/// cell in the same thread while holding the guard, that would dead lock.
+pub type StripedGuard<'a, T> = ReclaimGuard<'a, T, StripedReclaim>;
+
+/// The striped reader counters of [`StripedRcuCell`], the old value is
+/// handed out once all the readers that may see it are released
+#[derive(Debug)]
+pub struct StripedReclaim {
+    // the index of the counters that the new readers count on
+    index: AtomicUsize,
+    stripes: Box<[CachePadded<[AtomicUsize; 2]>]>,
+}
+
+impl<T> StripedRcuCell<T> {
+    /// the number of the stripes that the readers are spread over
+    #[inline]
+    pub fn stripes(&self) -> usize {
+        self.strategy().stripes.len()
+    }
+}
+
+impl<T> Strategy<T> for StripedReclaim {
+    const NAME: &'static str = "StripedRcuCell";
+
+    type Retired = ();
+
+    type Protect<'a> = Reader<'a>;
+
+    fn create() -> (Self, ()) {
+        let stripes = StripedReclaim {
+            index: AtomicUsize::new(0),
+            stripes: (0..stripes()).map(|_| CachePadded::default()).collect(),
+        };
+        (stripes, ())
+    }
+
+    // count the reader on the stripe of the thread and load the ptr
+    #[inline]
+    fn protect<'a>(&'a self, src: &AtomicPtr<T>) -> (*mut T, Reader<'a>) {
+        let stripe = &self.stripes[thread_stripe() & (self.stripes.len() - 1)];
+        let index = self.index.load(Ordering::Relaxed);
+        let count = &stripe[index];
+        // pairs with the SeqCst operations of the writer, either the writer
+        // sees the reader or the reader sees the new ptr
+        count.fetch_add(1, Ordering::SeqCst);
+        let ptr = src.load(Ordering::SeqCst);
+        (ptr, Reader { count })
+    }
+
+    // return the old value once all its readers are released
+    fn reclaim(&self, _: &mut (), old: Option<Arc<T>>) -> Option<Arc<T>> {
+        // the index is only changed under the lock
+        let index = self.index.load(Ordering::Relaxed);
+        // the late readers that loaded the other index before the last flip
+        // may still hold an old value, no new reader counts on it
+        self.drain(index ^ 1);
+        self.index.store(index ^ 1, Ordering::SeqCst);
+        // the new readers count on the other index now
+        self.drain(index);
+        old
+    }
+}
+
+impl StripedReclaim {
+    // wait the counters of the index in all the stripes to be zero
+    fn drain(&self, index: usize) {
+        for stripe in self.stripes.iter() {
+            let waiter = Waiter::<SpinThenYield>::new();
+            while stripe[index].load(Ordering::SeqCst) != 0 {
+                waiter.snooze();
+            }
+        }
+    }
+}
+
+/// A reader that is counted on a stripe, it's `pub` in the private module
+/// since the sealed strategy names it
+pub struct Reader<'a> {
+    count: &'a AtomicUsize,
+}
+
+impl Drop for Reader<'_> {
+    #[inline]
+    fn drop(&mut self) {
+        self.count.fetch_sub(1, Ordering::Release);
+    }
+}
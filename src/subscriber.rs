@@ -0,0 +1,141 @@
+use core::fmt;
+use core::ops::Deref;
+use core::task::{Context, Poll};
+
+use crate::pointer::RcuPointer;
+use crate::strategy::WaitStrategy;
+use crate::RcuCellOf;
+
+/// A subscriber that is notified when a new value is written to the cell,
+/// like the `Receiver` of `tokio::sync::watch` but runtime agnostic.
+///
+/// The subscriber remembers the version of the cell that it has seen, every
+/// `write`, `set`, `take`, `update` or successful compare and exchange on the
+/// cell makes it ready again. The cell could be borrowed or owned through any
+/// smart pointer, e.g. `Subscriber<Arc<RcuCell<T>>>` could be moved into a
+/// spawned task.
+///
+/// Like `watch`, only the latest value is observed, values that are written
+/// in quick succession may be skipped.
+///
+/// # Examples
+///
+/// ```
+/// use rcu_cell::{RcuCell, Subscriber};
+/// use std::sync::Arc;
+///
+/// let cell = Arc::new(RcuCell::new(1));
+/// let mut sub = Subscriber::new(cell.clone());
+/// assert!(!sub.has_changed());
+/// cell.write(2);
+/// assert!(sub.has_changed());
+/// assert_eq!(sub.read().as_deref(), Some(&2));
+/// assert!(!sub.has_changed());
+/// ```
+pub struct Subscriber<C> {
+    cell: C,
+    version: u64,
+}
+
+impl<P, W, C> Subscriber<C>
+where
+    P: RcuPointer,
+    W: WaitStrategy,
+    C: Deref<Target = RcuCellOf<P, W>>,
+{
+    /// subscribe to the cell, the current value is marked as seen
+    #[inline]
+    pub fn new(cell: C) -> Self {
+        let version = cell.version();
+        Subscriber { cell, version }
+    }
+
+    /// the cell that is subscribed
+    #[inline]
+    pub fn cell(&self) -> &RcuCellOf<P, W> {
+        &self.cell
+    }
+
+    /// check if a new value is written since the last seen one
+    #[inline]
+    pub fn has_changed(&self) -> bool {
+        self.cell.version() != self.version
+    }
+
+    /// read out the latest value and mark it as seen
+    #[inline]
+    pub fn read(&mut self) -> P
+    where
+        P: Clone,
+    {
+        let (value, version) = self.cell.read_versioned();
+        self.version = version;
+        value
+    }
+
+    /// mark the latest value as seen without reading it
+    #[inline]
+    pub fn mark_seen(&mut self) {
+        self.version = self.cell.version();
+    }
+
+    /// poll if a new value is written since the last seen one, the value is
+    /// marked as seen when `Ready` is returned
+    pub fn poll_changed(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if self.has_changed() {
+            self.mark_seen();
+            return Poll::Ready(());
+        }
+        self.cell.link.register_waker::<W>(cx.waker());
+        // check again in case the value is written before the registration
+        if self.has_changed() {
+            self.mark_seen();
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+
+    /// wait until a new value is written since the last seen one
+    pub async fn changed(&mut self) {
+        core::future::poll_fn(|cx| self.poll_changed(cx)).await
+    }
+
+    /// poll the next new value of the cell, this has the same signature as
+    /// `Stream::poll_next` so that it could be adapted to a `Stream` easily.
+    /// It never returns `Ready(None)` since the cell is kept alive by the
+    /// subscriber.
+    pub fn poll_next(&mut self, cx: &mut Context<'_>) -> Poll<Option<P>>
+    where
+        P: Clone,
+    {
+        match self.poll_changed(cx) {
+            Poll::Ready(()) => Poll::Ready(Some(self.read())),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// wait for the next new value of the cell
+    pub async fn next(&mut self) -> Option<P>
+    where
+        P: Clone,
+    {
+        core::future::poll_fn(|cx| self.poll_next(cx)).await
+    }
+}
+
+impl<C: Clone> Clone for Subscriber<C> {
+    fn clone(&self) -> Self {
+        Subscriber {
+            cell: self.cell.clone(),
+            version: self.version,
+        }
+    }
+}
+
+impl<C> fmt::Debug for Subscriber<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Subscriber")
+            .field("version", &self.version)
+            .finish()
+    }
+}
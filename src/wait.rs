@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use crate::error::RcuError;
+use crate::notify::thread_waker;
+use crate::park::Deadline;
+use crate::pointer::RcuPointer;
+use crate::strategy::WaitStrategy;
+use crate::RcuCellOf;
+
+impl<P: RcuPointer, W: WaitStrategy> RcuCellOf<P, W> {
+    /// block the current thread until a new value is written to the cell,
+    /// return the new value or `Timeout` if nothing is written in time.
+    ///
+    /// The thread is parked and woken by the writer, the writers only pay for
+    /// a null check when nobody is waiting on the cell.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rcu_cell::RcuCell;
+    /// use std::time::Duration;
+    ///
+    /// let cell = RcuCell::new(1);
+    /// std::thread::scope(|s| {
+    ///     s.spawn(|| {
+    ///         std::thread::sleep(Duration::from_millis(10));
+    ///         cell.write(2)
+    ///     });
+    ///     let v = cell.wait_for_change(Duration::from_secs(10)).unwrap();
+    ///     assert_eq!(v.as_deref(), Some(&2));
+    /// });
+    /// assert!(cell.wait_for_change(Duration::from_millis(1)).is_err());
+    /// ```
+    pub fn wait_for_change(&self, timeout: Duration) -> Result<P, RcuError>
+    where
+        P: Clone,
+    {
+        let deadline = Deadline::after(timeout);
+        let version = self.version();
+        self.wait_version(version, deadline)?;
+        Ok(self.read())
+    }
+
+    /// block the current thread until the value of the cell satisfies the
+    /// predicate, return the value or `Timeout` if it's not satisfied in time.
+    /// The predicate is checked with the current value first, then with each
+    /// new value that is written to the cell.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use rcu_cell::RcuCellNonNull;
+    /// use std::time::Duration;
+    ///
+    /// let cell = RcuCellNonNull::new(0);
+    /// std::thread::scope(|s| {
+    ///     s.spawn(|| (1..=10).for_each(|i| drop(cell.write(i))));
+    ///     let v = cell.wait_until(|v| **v == 10, Duration::from_secs(10)).unwrap();
+    ///     assert_eq!(*v, 10);
+    /// });
+    /// ```
+    pub fn wait_until<F>(&self, mut pred: F, timeout: Duration) -> Result<P, RcuError>
+    where
+        P: Clone,
+        F: FnMut(&P) -> bool,
+    {
+        let deadline = Deadline::after(timeout);
+        loop {
+            let (value, version) = self.read_versioned();
+            if pred(&value) {
+                return Ok(value);
+            }
+            self.wait_version(version, deadline)?;
+        }
+    }
+
+    // wait until the version of the cell is changed from `version`
+    fn wait_version(&self, version: u64, deadline: Deadline) -> Result<(), RcuError> {
+        if self.link.changed_since(version) {
+            return Ok(());
+        }
+        let waker = thread_waker();
+        loop {
+            // the waker is consumed by each publish, register it again
+            // after any wake up, spurious or not
+            self.link.register_waker::<W>(&waker);
+            if self.link.changed_since(version) {
+                return Ok(());
+            }
+            deadline.park()?;
+        }
+    }
+}